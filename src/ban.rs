@@ -0,0 +1,154 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use tokio::sync::RwLock;
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::config::BanPolicy;
+
+struct BanEntry {
+    hits: VecDeque<Instant>,
+    banned_until: Option<Instant>,
+}
+
+/// An in-memory fail2ban-style table fed by filter verdicts: every time a
+/// Python filter flags a flow, its client IP gets a strike, and once it
+/// accumulates more than [`BanPolicy::threshold`] strikes within
+/// [`BanPolicy::window`] it is banned until `now + ttl`. Consulted by
+/// [`crate::proxy::Proxy`] at accept time so a banned IP never reaches the
+/// upstream.
+pub struct BanList {
+    policy: BanPolicy,
+    entries: RwLock<HashMap<IpAddr, BanEntry>>,
+}
+
+impl BanList {
+    pub fn new(policy: BanPolicy) -> BanList {
+        BanList {
+            policy,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Records a filter verdict against `ip`, banning it once it crosses
+    /// `threshold` strikes within `window`. A no-op when banning is disabled.
+    pub async fn flag(&self, ip: IpAddr) {
+        if !self.policy.enabled {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut entries = self.entries.write().await;
+        let entry = entries.entry(ip).or_insert_with(|| BanEntry {
+            hits: VecDeque::new(),
+            banned_until: None,
+        });
+
+        entry.hits.push_back(now);
+        while entry
+            .hits
+            .front()
+            .is_some_and(|hit| now.duration_since(*hit) > self.policy.window)
+        {
+            entry.hits.pop_front();
+        }
+
+        if entry.hits.len() > self.policy.threshold && entry.banned_until.is_none() {
+            info!(
+                "Banning {} for {:?} after {} flagged flows in {:?}",
+                ip,
+                self.policy.ttl,
+                entry.hits.len(),
+                self.policy.window
+            );
+            entry.banned_until = Some(now + self.policy.ttl);
+        }
+    }
+
+    /// Bans `ip` immediately, bypassing the strike threshold — used when a
+    /// filter returns an explicit ban verdict rather than a plain drop.
+    pub async fn ban_now(&self, ip: IpAddr) {
+        if !self.policy.enabled {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut entries = self.entries.write().await;
+        let entry = entries.entry(ip).or_insert_with(|| BanEntry {
+            hits: VecDeque::new(),
+            banned_until: None,
+        });
+
+        info!("Banning {} for {:?} (explicit filter verdict)", ip, self.policy.ttl);
+        entry.banned_until = Some(now + self.policy.ttl);
+    }
+
+    /// Returns whether `ip` is currently banned. Always `false` when banning
+    /// is disabled.
+    pub async fn is_banned(&self, ip: IpAddr) -> bool {
+        if !self.policy.enabled {
+            return false;
+        }
+
+        self.entries
+            .read()
+            .await
+            .get(&ip)
+            .and_then(|entry| entry.banned_until)
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Drops expired bans and stale hit histories so the table doesn't grow
+    /// unbounded, then re-exports the active ban set if `export_path` is set.
+    async fn expire(&self) {
+        let now = Instant::now();
+        let banned = {
+            let mut entries = self.entries.write().await;
+            entries.retain(|_, entry| {
+                while entry
+                    .hits
+                    .front()
+                    .is_some_and(|hit| now.duration_since(*hit) > self.policy.window)
+                {
+                    entry.hits.pop_front();
+                }
+
+                match entry.banned_until {
+                    Some(until) => now < until,
+                    None => !entry.hits.is_empty(),
+                }
+            });
+
+            entries
+                .iter()
+                .filter(|(_, entry)| entry.banned_until.is_some())
+                .map(|(ip, _)| ip.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let Some(ref path) = self.policy.export_path else {
+            return;
+        };
+
+        if let Err(e) = tokio::fs::write(path, banned.join("\n")).await {
+            warn!("Failed to export ban list to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Runs forever, periodically pruning expired bans and re-exporting the
+    /// active set. Spawned once at startup; returns immediately when banning
+    /// is disabled.
+    pub async fn run_expirer(&self) {
+        if !self.policy.enabled {
+            return;
+        }
+
+        let mut interval = time::interval(self.policy.window.max(Duration::from_secs(1)));
+        loop {
+            interval.tick().await;
+            self.expire().await;
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::client::conn::http1::SendRequest;
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use rustls::ClientConfig;
+use rustls::RootCertStore;
+use rustls::pki_types::ServerName;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time;
+use tokio_rustls::TlsConnector;
+use tracing::{debug, warn};
+
+use crate::compress;
+use crate::http::{BytesBody, HttpRequest, HttpResponse};
+
+type ClientStream = Pin<Box<dyn AsyncRead + AsyncWrite + Send + Sync + Unpin>>;
+type ClientConnTask = JoinHandle<hyper::Result<()>>;
+
+const MAX_IDLE_PER_HOST: usize = 8;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+struct PooledConn {
+    sender: SendRequest<BytesBody>,
+    upstream: ClientConnTask,
+    idle_since: Instant,
+}
+
+/// A generic, pooled HTTP/1.1 client Python filters can use to make side
+/// requests (e.g. to an auth service or a honeypot-scoring endpoint)
+/// without blocking the proxy's own flows. Pools connections per
+/// destination authority the same way `proxy::connector::Connector` pools
+/// connections to the configured upstream, but dials out to whatever host
+/// the filter asks for.
+pub struct OutboundClient {
+    tls_connector: TlsConnector,
+    pool: Mutex<HashMap<String, Vec<PooledConn>>>,
+    max_body: u64,
+    timeout: Duration,
+}
+
+impl OutboundClient {
+    pub fn new(max_body: u64, timeout: Duration) -> anyhow::Result<OutboundClient> {
+        let mut root_store = RootCertStore::empty();
+        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+        let mut client_config = ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        client_config.alpn_protocols = vec!["http/1.1".into()];
+
+        Ok(OutboundClient {
+            tls_connector: TlsConnector::from(Arc::new(client_config)),
+            pool: Mutex::new(HashMap::new()),
+            max_body,
+            timeout,
+        })
+    }
+
+    /// Sends a request to whatever host its own URI points at, reusing a
+    /// pooled connection to that host when one is idle.
+    pub async fn send(&self, req: HttpRequest) -> anyhow::Result<HttpResponse> {
+        let HttpRequest(req, _) = req;
+
+        let authority = req
+            .uri()
+            .authority()
+            .ok_or_else(|| anyhow::anyhow!("Request URI is missing a host"))?
+            .clone();
+        let https = req.uri().scheme_str() == Some("https");
+        let host = authority.host().to_string();
+        let port = authority.port_u16().unwrap_or(if https { 443 } else { 80 });
+        let key = format!("{}|{}|{}", host, port, https);
+
+        let (mut sender, upstream) = match self.take_pooled(&key).await {
+            Some(conn) => conn,
+            None => self.connect(&host, port, https).await?,
+        };
+
+        let (parts, body) = req.into_parts();
+        let wire_req = Request::from_parts(parts, Self::full(body));
+
+        let resp = match time::timeout(self.timeout, sender.send_request(wire_req)).await {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(e)) => {
+                upstream.abort();
+                anyhow::bail!("Outbound request failed: {}", e);
+            }
+            Err(_) => {
+                upstream.abort();
+                anyhow::bail!("Outbound request timed out");
+            }
+        };
+
+        let (parts, incoming) = resp.into_parts();
+        let body = match Limited::new(incoming, self.max_body as usize).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(_) => {
+                upstream.abort();
+                anyhow::bail!("Outbound response body too big");
+            }
+        };
+
+        let (body, coding) =
+            match compress::decode_body(&parts.headers, body, self.max_body as usize) {
+                Ok(pair) => pair,
+                Err(_) => {
+                    upstream.abort();
+                    anyhow::bail!("Outbound response body too big");
+                }
+            };
+
+        self.release(key, sender, upstream).await;
+
+        Ok(HttpResponse(Response::from_parts(parts, body), coding))
+    }
+
+    async fn take_pooled(&self, key: &str) -> Option<(SendRequest<BytesBody>, ClientConnTask)> {
+        let mut pool = self.pool.lock().await;
+        let conns = pool.get_mut(key)?;
+
+        while let Some(conn) = conns.pop() {
+            if conn.sender.is_closed() || conn.idle_since.elapsed() >= IDLE_TIMEOUT {
+                conn.upstream.abort();
+                continue;
+            }
+
+            return Some((conn.sender, conn.upstream));
+        }
+
+        None
+    }
+
+    async fn release(&self, key: String, sender: SendRequest<BytesBody>, upstream: ClientConnTask) {
+        if sender.is_closed() {
+            upstream.abort();
+            return;
+        }
+
+        let mut pool = self.pool.lock().await;
+        let conns = pool.entry(key).or_default();
+
+        conns.retain(|conn| {
+            let keep = !conn.sender.is_closed() && conn.idle_since.elapsed() < IDLE_TIMEOUT;
+            if !keep {
+                conn.upstream.abort();
+            }
+            keep
+        });
+
+        if conns.len() >= MAX_IDLE_PER_HOST {
+            upstream.abort();
+        } else {
+            conns.push(PooledConn {
+                sender,
+                upstream,
+                idle_since: Instant::now(),
+            });
+        }
+    }
+
+    async fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        https: bool,
+    ) -> anyhow::Result<(SendRequest<BytesBody>, ClientConnTask)> {
+        debug!("Outbound client connecting to {}:{}", host, port);
+        let stream = TcpStream::connect((host, port)).await?;
+
+        let stream: ClientStream = if https {
+            let server_name = ServerName::try_from(host.to_string())?;
+            Box::pin(self.tls_connector.connect(server_name, stream).await?)
+        } else {
+            Box::pin(stream)
+        };
+
+        let (sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        let upstream = tokio::spawn(conn);
+        Ok((sender, upstream))
+    }
+
+    fn full(body: Bytes) -> BytesBody {
+        Full::new(body).map_err(|never| match never {}).boxed()
+    }
+}
+
+static CLIENT: OnceLock<Arc<OutboundClient>> = OnceLock::new();
+
+/// Initializes the process-wide outbound client `api::PyClient` calls
+/// into, bounding its bodies and per-call timeout the same way the proxy
+/// bounds its own upstream traffic. Safe to call at most once; later calls
+/// are ignored.
+pub fn init(max_body: u64, timeout: Duration) {
+    let client = match OutboundClient::new(max_body, timeout) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed to initialize outbound HTTP client: {}", e);
+            return;
+        }
+    };
+
+    if CLIENT.set(Arc::new(client)).is_err() {
+        warn!("Outbound HTTP client was already initialized");
+    }
+}
+
+pub fn get() -> Option<Arc<OutboundClient>> {
+    CLIENT.get().cloned()
+}
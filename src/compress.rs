@@ -0,0 +1,106 @@
+use bytes::Bytes;
+use std::io::{Read, Write};
+
+/// A `Content-Encoding` this proxy knows how to transparently decompress for
+/// filters and re-compress afterwards, mirroring actix-web's brotli/
+/// flate2-zlib feature set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentCoding {
+    Gzip,
+    Deflate,
+    Brotli,
+}
+
+impl ContentCoding {
+    /// Parses a `Content-Encoding` header value, ignoring codings we don't
+    /// support (e.g. `identity`, `compress`).
+    pub fn from_header(value: &str) -> Option<ContentCoding> {
+        match value.trim() {
+            "gzip" | "x-gzip" => Some(ContentCoding::Gzip),
+            "deflate" => Some(ContentCoding::Deflate),
+            "br" => Some(ContentCoding::Brotli),
+            _ => None,
+        }
+    }
+
+    pub fn as_header_value(self) -> &'static str {
+        match self {
+            ContentCoding::Gzip => "gzip",
+            ContentCoding::Deflate => "deflate",
+            ContentCoding::Brotli => "br",
+        }
+    }
+}
+
+/// Looks at the `Content-Encoding` header and decompresses `body` if it
+/// names a coding we understand, bounding the inflated size at `max_size`
+/// to guard against decompression bombs. Returns the body unchanged,
+/// alongside `None`, if there's no recognized coding to undo.
+pub fn decode_body(
+    headers: &http::HeaderMap,
+    body: Bytes,
+    max_size: usize,
+) -> anyhow::Result<(Bytes, Option<ContentCoding>)> {
+    let Some(coding) = headers
+        .get(http::header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .and_then(ContentCoding::from_header)
+    else {
+        return Ok((body, None));
+    };
+
+    Ok((decode(coding, &body, max_size)?, Some(coding)))
+}
+
+/// Decompresses `body` with `coding`, erroring out if the inflated size
+/// would exceed `max_size`.
+pub fn decode(coding: ContentCoding, body: &[u8], max_size: usize) -> anyhow::Result<Bytes> {
+    let mut out = Vec::new();
+
+    let read = match coding {
+        ContentCoding::Gzip => flate2::read::GzDecoder::new(body)
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut out),
+        ContentCoding::Deflate => flate2::read::ZlibDecoder::new(body)
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut out),
+        ContentCoding::Brotli => brotli::Decompressor::new(body, 4096)
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut out),
+    };
+
+    read.map_err(|e| anyhow::anyhow!("Failed to decompress {:?} body: {}", coding, e))?;
+
+    if out.len() > max_size {
+        anyhow::bail!("Decompressed body exceeds the {} byte limit", max_size);
+    }
+
+    Ok(Bytes::from(out))
+}
+
+/// Re-compresses `body` with `coding`, the inverse of [`decode`].
+pub fn encode(coding: ContentCoding, body: &[u8]) -> anyhow::Result<Bytes> {
+    let mut out = Vec::new();
+
+    match coding {
+        ContentCoding::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(&mut out, flate2::Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()?;
+        }
+        ContentCoding::Deflate => {
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(&mut out, flate2::Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()?;
+        }
+        ContentCoding::Brotli => {
+            let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+            writer.write_all(body)?;
+            writer.flush()?;
+        }
+    }
+
+    Ok(Bytes::from(out))
+}
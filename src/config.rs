@@ -36,6 +36,13 @@ pub struct Config {
     )]
     pub server_timeout: Duration,
 
+    #[serde(
+        alias = "from_body_timeout",
+        default = "default_client_body_timeout",
+        with = "humantime_serde"
+    )]
+    pub client_body_timeout: Duration,
+
     #[serde(alias = "from_max_history", default = "default_max_history")]
     pub client_max_history: Byte,
 
@@ -53,8 +60,57 @@ pub struct Config {
     #[serde(default)]
     pub tls_ca_file: Option<String>,
 
-    #[serde(default, rename = "script_path")]
-    pub python_script: Option<String>,
+    /// Whether the client side actually validates the upstream's certificate
+    /// (chain, expiry, and SNI hostname match) against `tls_ca_file` (or the
+    /// bundled `webpki-roots` set). Set to `false` to keep the old behavior of accepting
+    /// any certificate, useful for CTF/debug setups with self-signed certs
+    /// and no shared CA.
+    #[serde(default = "default_tls_verify")]
+    pub tls_verify: bool,
+
+    /// Whether the listening side requires the connecting client to present
+    /// a certificate signed by `tls_ca_file`, for mutual TLS setups.
+    #[serde(default)]
+    pub tls_client_auth: bool,
+
+    /// ALPN protocols to advertise, in preference order (e.g. `["h2",
+    /// "http/1.1"]`). Whatever the client actually negotiates on accept is
+    /// mirrored into the upstream handshake, so this list only needs to
+    /// cover what the real backend(s) understand.
+    #[serde(default = "default_tls_alpn")]
+    pub tls_alpn: Vec<String>,
+
+    /// Additional certificates to serve for other hostnames sharing this
+    /// listener, matched against the SNI the client sent. `tls_cert_file`/
+    /// `tls_key_file` remain the default, served when no pattern matches
+    /// (or the client sent no SNI at all). See `crate::tls`.
+    #[serde(default)]
+    pub tls_sni_certs: Vec<SniCert>,
+
+    /// How the upstream leg handles TLS when `tls_enabled` is on: `reencrypt`
+    /// (default) opens a fresh TLS connection to the backend with
+    /// `tls_client_config`/`tls_verify` applied, `terminate` decrypts the
+    /// client and dials the backend in plaintext. There's no separate
+    /// passthrough mode — leave `tls_enabled` off for that, which already
+    /// relays bytes on both legs without ever touching a TLS record.
+    #[serde(default)]
+    pub upstream_tls: UpstreamTls,
+
+    /// Additionally appends per-handshake TLS secrets in NSS `SSLKEYLOGFILE`
+    /// format to this file, for both the client-facing and upstream legs.
+    /// `proxy::dumper`'s pcapng captures also carry these secrets in a
+    /// Decryption Secrets Block, written once when each capture file is
+    /// opened and so holding only handshakes made before then; this file is
+    /// for decrypting an independently captured trace (e.g. a real
+    /// `tcpdump` run alongside this proxy) in Wireshark. Leave unset to
+    /// disable the extra file.
+    #[serde(default)]
+    pub tls_keylog_path: Option<PathBuf>,
+
+    /// Directory of ordered Python filter stages (`*.py`, applied in file
+    /// name order). See `filter::chain`.
+    #[serde(default)]
+    pub filter_dir: Option<PathBuf>,
 
     pub dump_enabled: bool,
 
@@ -67,12 +123,218 @@ pub struct Config {
 
     #[serde(default = "default_max_packets")]
     pub dump_max_packets: usize,
+
+    #[serde(default)]
+    pub proxy_protocol: ProxyProtocol,
+
+    /// Caps how many TCP connections can be in the middle of a TLS
+    /// handshake at once. The handshake itself (CPU-bound, attacker-
+    /// controlled pacing) is gated behind this many permits so a burst of
+    /// slow or malicious handshakes can't starve it of resources; while all
+    /// permits are held, the accept loop stops calling `accept(2)` and
+    /// further connections wait in the kernel's backlog. Has no effect when `tls_enabled` is off, since there's no
+    /// handshake to bound.
+    #[serde(default = "default_max_pending_handshakes")]
+    pub max_pending_handshakes: usize,
+
+    /// Which transport the listener speaks. `quic` reuses the same TLS
+    /// material as the TCP path (wrapped for QUIC) but splices each
+    /// multiplexed stream into its own synthetic raw flow instead of one
+    /// flow per connection. See `proxy::quic`.
+    #[serde(default)]
+    pub transport: Transport,
+
+    /// What this service's backend actually speaks, for the purposes of
+    /// [`crate::proxy::dumper::Dumper`] reconstructing it into a pcap:
+    /// `tcp` synthesizes a handshake and sequences bytes as segments, `udp`
+    /// emits one datagram per recorded chunk (several only for a chunk
+    /// larger than the MTU) with no handshake/seq-ack
+    /// bookkeeping (the common case for VPN/tunnel-style raw flows).
+    #[serde(default)]
+    pub dump_protocol: DumpProtocol,
+
+    #[serde(default)]
+    pub connect_retry: RetryPolicy,
+
+    #[serde(default = "default_http_max_idle_per_host")]
+    pub http_max_idle_per_host: usize,
+
+    #[serde(default = "default_http_idle_timeout", with = "humantime_serde")]
+    pub http_idle_timeout: Duration,
+
+    #[serde(default)]
+    pub ban_policy: BanPolicy,
+
+    /// Directory to write one timed replay cast per closed flow into (see
+    /// `proxy::record`). Leave unset to disable recording.
+    #[serde(default)]
+    pub record_path: Option<PathBuf>,
+
+    /// How long to wait for in-flight flows to drain on SIGINT/SIGTERM
+    /// before forcing the remaining ones closed (see `crate::shutdown`).
+    #[serde(default = "default_shutdown_grace", with = "humantime_serde")]
+    pub shutdown_grace: Duration,
+
+    /// Maximum time a single Python filter hook may run before it is
+    /// interrupted and the flow it was handling is dropped.
+    #[serde(default = "default_filter_deadline", with = "humantime_serde")]
+    pub filter_deadline: Duration,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyProtocol {
+    #[default]
+    Off,
+    V1,
+    V2,
+}
+
+/// Selects the transport a service's listener/connector pair speaks. See
+/// `proxy::quic` for the `Quic` variant's scope (no datagrams, no 0-RTT).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Tcp,
+    Quic,
+}
+
+/// The layer-4 protocol a raw flow should be reconstructed as when dumped
+/// to a pcap. See [`Config::dump_protocol`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DumpProtocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+/// How `Connector` treats the upstream leg of a TLS service. See
+/// [`Config::upstream_tls`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamTls {
+    #[default]
+    Reencrypt,
+    Terminate,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RetryPolicy {
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+
+    #[serde(default = "default_initial_backoff", with = "humantime_serde")]
+    pub initial_backoff: Duration,
+
+    #[serde(default = "default_max_backoff", with = "humantime_serde")]
+    pub max_backoff: Duration,
+
+    #[serde(default = "default_backoff_multiplier")]
+    pub multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: default_max_retries(),
+            initial_backoff: default_initial_backoff(),
+            max_backoff: default_max_backoff(),
+            multiplier: default_backoff_multiplier(),
+        }
+    }
+}
+
+fn default_max_retries() -> u32 {
+    0
+}
+
+fn default_initial_backoff() -> Duration {
+    Duration::from_millis(100)
+}
+
+fn default_max_backoff() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+/// One extra certificate a TLS listener can route to by SNI hostname, on
+/// top of the default `tls_cert_file`/`tls_key_file` pair. See `crate::tls`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SniCert {
+    /// Hostname to match, with an optional single leading `*.` wildcard
+    /// (e.g. `*.example.com`).
+    pub sni_pattern: String,
+    pub cert_file: String,
+    pub key_file: String,
+}
+
+/// A fail2ban-style policy: an IP is banned once a Python filter flags it
+/// more than `threshold` times within `window`, and stays banned for `ttl`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BanPolicy {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default = "default_ban_window", with = "humantime_serde")]
+    pub window: Duration,
+
+    #[serde(default = "default_ban_threshold")]
+    pub threshold: usize,
+
+    #[serde(default = "default_ban_ttl", with = "humantime_serde")]
+    pub ttl: Duration,
+
+    /// Where to write the active ban set, one IP per line, so other tooling
+    /// (e.g. an nftables set reload script) can consume it.
+    #[serde(default)]
+    pub export_path: Option<PathBuf>,
+}
+
+impl Default for BanPolicy {
+    fn default() -> Self {
+        BanPolicy {
+            enabled: false,
+            window: default_ban_window(),
+            threshold: default_ban_threshold(),
+            ttl: default_ban_ttl(),
+            export_path: None,
+        }
+    }
+}
+
+fn default_ban_window() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_ban_threshold() -> usize {
+    5
+}
+
+fn default_ban_ttl() -> Duration {
+    Duration::from_secs(600)
 }
 
 fn default_timeout() -> Duration {
     Duration::from_secs(30)
 }
 
+fn default_client_body_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_tls_verify() -> bool {
+    true
+}
+
+fn default_tls_alpn() -> Vec<String> {
+    vec!["http/1.1".to_string()]
+}
+
 fn default_max_history() -> Byte {
     Byte::from_u64_with_unit(512, Unit::MiB).unwrap()
 }
@@ -81,6 +343,26 @@ fn default_max_packets() -> usize {
     512
 }
 
+fn default_max_pending_handshakes() -> usize {
+    256
+}
+
+fn default_http_max_idle_per_host() -> usize {
+    8
+}
+
+fn default_http_idle_timeout() -> Duration {
+    Duration::from_secs(90)
+}
+
+fn default_shutdown_grace() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_filter_deadline() -> Duration {
+    Duration::from_millis(500)
+}
+
 impl Config {
     pub fn load_from_file(path: &str) -> anyhow::Result<Config> {
         let reader = BufReader::new(File::open(path)?);
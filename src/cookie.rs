@@ -0,0 +1,166 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// The `SameSite` attribute of a `Set-Cookie` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    pub fn from_str(s: &str) -> Option<SameSite> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Some(SameSite::Strict),
+            "lax" => Some(SameSite::Lax),
+            "none" => Some(SameSite::None),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// A single cookie, parsed out of (or about to be serialized into) a
+/// `Set-Cookie` header.
+#[derive(Debug, Clone)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: Option<String>,
+    pub path: Option<String>,
+    pub expires: Option<DateTime<Utc>>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    pub fn new(name: String, value: String) -> Cookie {
+        Cookie {
+            name,
+            value,
+            domain: None,
+            path: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Parses a single `Set-Cookie` header value into a [`Cookie`], in the
+    /// style of the `CookieJar` parser actix-web ships with.
+    pub fn parse(value: &str) -> Option<Cookie> {
+        let mut parts = value.split(';').map(str::trim);
+        let (name, value) = parts.next()?.split_once('=')?;
+
+        let mut cookie = Cookie::new(name.trim().to_string(), percent_decode(value.trim()));
+
+        for attr in parts {
+            let (key, val) = attr.split_once('=').unwrap_or((attr, ""));
+            match key.to_ascii_lowercase().as_str() {
+                "domain" => cookie.domain = Some(val.to_string()),
+                "path" => cookie.path = Some(val.to_string()),
+                "expires" => {
+                    cookie.expires = DateTime::parse_from_rfc2822(val)
+                        .ok()
+                        .map(|dt| dt.with_timezone(&Utc))
+                }
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => cookie.same_site = SameSite::from_str(val),
+                _ => (),
+            }
+        }
+
+        Some(cookie)
+    }
+
+    /// Serializes this cookie back into a `Set-Cookie` header value.
+    pub fn to_header_value(&self) -> String {
+        let mut out = format!("{}={}", self.name, percent_encode(&self.value));
+
+        if let Some(ref domain) = self.domain {
+            out.push_str(&format!("; Domain={}", domain));
+        }
+        if let Some(ref path) = self.path {
+            out.push_str(&format!("; Path={}", path));
+        }
+        if let Some(expires) = self.expires {
+            out.push_str(&format!(
+                "; Expires={}",
+                expires.format("%a, %d %b %Y %H:%M:%S GMT")
+            ));
+        }
+        if self.secure {
+            out.push_str("; Secure");
+        }
+        if self.http_only {
+            out.push_str("; HttpOnly");
+        }
+        if let Some(same_site) = self.same_site {
+            out.push_str(&format!("; SameSite={}", same_site.as_str()));
+        }
+
+        out
+    }
+}
+
+/// Parses a request `Cookie` header (`name=value; name2=value2`) into a
+/// name/value map, percent-decoding each value.
+pub fn parse_cookie_header(value: &str) -> HashMap<String, String> {
+    value
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), percent_decode(value.trim())))
+        .collect()
+}
+
+/// Serializes a name/value map back into a single `Cookie` header value.
+pub fn format_cookie_header(cookies: &HashMap<String, String>) -> String {
+    cookies
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, percent_encode(value)))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn percent_decode(value: &str) -> String {
+    let mut out = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let digit = |b: Option<u8>| b.and_then(|b| (b as char).to_digit(16));
+            match (digit(bytes.next()), digit(bytes.next())) {
+                (Some(hi), Some(lo)) => out.push((hi * 16 + lo) as u8),
+                _ => out.push(b'%'),
+            }
+        } else {
+            out.push(b);
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
@@ -1,13 +1,30 @@
 use chrono::{DateTime, Utc};
 use http::Uri;
+use http::header::{COOKIE, SET_COOKIE};
 use pyo3::types::{PyBytes, PyDict, PyList, PyString};
 use pyo3::{PyTraverseError, PyVisit, prelude::*};
+use rustls::pki_types::CertificateDer;
 use uuid::Uuid;
 
+use crate::compress::ContentCoding;
+use crate::cookie::{self, Cookie, SameSite};
 use crate::http::{HttpRequest, HttpResponse};
+use crate::ws::WsFrame;
 
 // TODO: Add a way to convert lazily into python object
 
+/// Converts a TLS peer certificate chain into a list of `bytes`, one DER
+/// blob per certificate, for exposure to Python as `peer_cert_chain`.
+fn cert_chain_to_pylist(
+    py: Python<'_>,
+    chain: Option<&[CertificateDer<'static>]>,
+) -> Option<Py<PyList>> {
+    let chain = chain?;
+    PyList::new(py, chain.iter().map(|cert| PyBytes::new(py, cert.as_ref())))
+        .ok()
+        .map(Into::into)
+}
+
 #[pyclass(module = "proxad", name = "RawFlow", frozen, dict, freelist = 64)]
 pub struct PyRawFlow {
     /// Unique id of this flow
@@ -21,6 +38,16 @@ pub struct PyRawFlow {
     /// All the bytes sent by the server so far
     #[pyo3(get)]
     server_history: Option<Py<PyBytes>>,
+
+    /// DER-encoded certificate chain the client presented during the TLS
+    /// handshake, when `tls_client_auth` is enabled. `None` otherwise.
+    #[pyo3(get)]
+    peer_cert_chain: Option<Py<PyList>>,
+
+    /// Local sequence number of the QUIC stream this flow was synthesized
+    /// for, when the service's `transport` is `quic`. `None` over plain TCP.
+    #[pyo3(get)]
+    stream_id: Option<u64>,
 }
 
 #[pymethods]
@@ -32,24 +59,35 @@ impl PyRawFlow {
     fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
         visit.call(&self.client_history)?;
         visit.call(&self.server_history)?;
+        visit.call(&self.peer_cert_chain)?;
         Ok(())
     }
 }
 
 impl PyRawFlow {
-    pub fn new_empty(id: Uuid) -> Self {
+    pub fn new_empty(id: Uuid, stream_id: Option<u64>) -> Self {
         PyRawFlow {
             id,
             client_history: None,
             server_history: None,
+            peer_cert_chain: None,
+            stream_id,
         }
     }
 
-    pub fn new(id: Uuid, client_history: &[u8], server_history: &[u8]) -> Self {
+    pub fn new(
+        id: Uuid,
+        client_history: &[u8],
+        server_history: &[u8],
+        peer_cert_chain: Option<&[CertificateDer<'static>]>,
+        stream_id: Option<u64>,
+    ) -> Self {
         Python::with_gil(|py| PyRawFlow {
             id,
             client_history: Some(PyBytes::new(py, client_history).into()),
             server_history: Some(PyBytes::new(py, server_history).into()),
+            peer_cert_chain: cert_chain_to_pylist(py, peer_cert_chain),
+            stream_id,
         })
     }
 }
@@ -71,6 +109,17 @@ pub struct PyHttpFlow {
     /// Receive time of the last response
     #[pyo3(get)]
     pub response_time: Option<DateTime<Utc>>,
+
+    /// HTTP/2 stream id this request/response pair belongs to. Always 0
+    /// today, since the HTTP path only speaks HTTP/1.1; reserved for when
+    /// a connection can carry more than one concurrent stream.
+    #[pyo3(get)]
+    pub stream_id: u32,
+
+    /// DER-encoded certificate chain the client presented during the TLS
+    /// handshake, when `tls_client_auth` is enabled. `None` otherwise.
+    #[pyo3(get)]
+    pub peer_cert_chain: Option<Py<PyList>>,
 }
 
 #[pymethods]
@@ -78,6 +127,11 @@ impl PyHttpFlow {
     fn __str__(&self) -> String {
         format!("HttpFlow(id={})", self.id)
     }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.peer_cert_chain)?;
+        Ok(())
+    }
 }
 
 impl PyHttpFlow {
@@ -86,13 +140,17 @@ impl PyHttpFlow {
         start: DateTime<Utc>,
         last_req: Option<DateTime<Utc>>,
         last_resp: Option<DateTime<Utc>>,
+        stream_id: u32,
+        peer_cert_chain: Option<&[CertificateDer<'static>]>,
     ) -> Self {
-        PyHttpFlow {
+        Python::with_gil(|py| PyHttpFlow {
             id,
             start_time: start,
             request_time: last_req,
             response_time: last_resp,
-        }
+            stream_id,
+            peer_cert_chain: cert_chain_to_pylist(py, peer_cert_chain),
+        })
     }
 }
 
@@ -123,6 +181,8 @@ pub struct PyHttpResp {
     pub body: Option<Py<PyBytes>>,
     #[pyo3(set)]
     pub status: Option<u16>,
+    #[pyo3(set)]
+    pub cookies: Option<Py<PyList>>,
 }
 
 #[pymethods]
@@ -135,6 +195,7 @@ impl PyHttpResp {
             headers: Some(headers),
             body: Some(body),
             status: Some(status),
+            cookies: None,
         }
     }
 
@@ -192,6 +253,37 @@ impl PyHttpResp {
         })
     }
 
+    /// Returns the `Content-Encoding` the response arrived under, if the
+    /// body was transparently decompressed for this filter to see.
+    #[getter]
+    fn get_content_encoding(&self) -> Option<&'static str> {
+        self.resp
+            .as_ref()
+            .and_then(|r| r.1)
+            .map(ContentCoding::as_header_value)
+    }
+
+    /// Returns the `Set-Cookie` headers of this response, parsed into
+    /// structured `Cookie` objects
+    #[getter]
+    fn get_cookies(&mut self, py: Python<'_>) -> PyResult<Py<PyList>> {
+        cached_getter!(self, py, resp, cookies, {
+            let list = PyList::empty(py);
+            for value in resp.0.headers().get_all(SET_COOKIE) {
+                let value = value.to_str().map_err(|e| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Invalid Set-Cookie header: {}",
+                        e
+                    ))
+                })?;
+                if let Some(cookie) = Cookie::parse(value) {
+                    list.append(Py::new(py, PyCookie::new(cookie))?)?;
+                }
+            }
+            <Py<PyList>>::from(list)
+        })
+    }
+
     /// Returns the status code of this response
     #[getter]
     fn get_status(&mut self) -> PyResult<u16> {
@@ -230,6 +322,7 @@ impl PyHttpResp {
         visit.call(&self.headers)?;
         visit.call(&self.body)?;
         visit.call(&self.raw)?;
+        visit.call(&self.cookies)?;
         Ok(())
     }
 
@@ -237,6 +330,7 @@ impl PyHttpResp {
         self.headers = None;
         self.body = None;
         self.raw = None;
+        self.cookies = None;
     }
 }
 
@@ -248,6 +342,7 @@ impl PyHttpResp {
             headers: None,
             body: None,
             status: None,
+            cookies: None,
         }
     }
 }
@@ -265,6 +360,8 @@ pub struct PyHttpReq {
     pub method: Option<Py<PyString>>,
     #[pyo3(set)]
     pub uri: Option<Py<PyUri>>,
+    #[pyo3(set)]
+    pub cookies: Option<Py<PyDict>>,
 }
 
 #[pymethods]
@@ -283,6 +380,7 @@ impl PyHttpReq {
             body: Some(body),
             method: Some(method),
             uri: Some(uri),
+            cookies: None,
         }
     }
 
@@ -355,6 +453,37 @@ impl PyHttpReq {
         })
     }
 
+    /// Returns the `Content-Encoding` the request arrived under, if the
+    /// body was transparently decompressed for this filter to see.
+    #[getter]
+    fn get_content_encoding(&self) -> Option<&'static str> {
+        self.req
+            .as_ref()
+            .and_then(|r| r.1)
+            .map(ContentCoding::as_header_value)
+    }
+
+    /// Returns the request's cookies (parsed `Cookie` header) as a
+    /// dict[str, str]
+    #[getter]
+    fn get_cookies(&mut self, py: Python<'_>) -> PyResult<Py<PyDict>> {
+        cached_getter!(self, py, req, cookies, {
+            let dict = PyDict::new(py);
+            if let Some(value) = req.0.headers().get(COOKIE) {
+                let value = value.to_str().map_err(|e| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Invalid Cookie header: {}",
+                        e
+                    ))
+                })?;
+                for (name, value) in cookie::parse_cookie_header(value) {
+                    dict.set_item(name, value)?;
+                }
+            }
+            <Py<PyDict>>::from(dict)
+        })
+    }
+
     /// Returns the method of this request
     #[getter]
     fn get_method(&mut self, py: Python<'_>) -> PyResult<Py<PyString>> {
@@ -393,6 +522,7 @@ impl PyHttpReq {
         visit.call(&self.method)?;
         visit.call(&self.uri)?;
         visit.call(&self.raw)?;
+        visit.call(&self.cookies)?;
         Ok(())
     }
 
@@ -402,6 +532,7 @@ impl PyHttpReq {
         self.method = None;
         self.uri = None;
         self.raw = None;
+        self.cookies = None;
     }
 }
 
@@ -414,6 +545,210 @@ impl PyHttpReq {
             body: None,
             method: None,
             uri: None,
+            cookies: None,
+        }
+    }
+}
+
+#[pyclass(module = "proxad", name = "Cookie", freelist = 64)]
+pub struct PyCookie {
+    pub cookie: Cookie,
+}
+
+#[pymethods]
+impl PyCookie {
+    #[new]
+    #[pyo3(signature = (name, value, domain=None, path=None, secure=false, httponly=false, samesite=None))]
+    pub fn py_new(
+        name: String,
+        value: String,
+        domain: Option<String>,
+        path: Option<String>,
+        secure: bool,
+        httponly: bool,
+        samesite: Option<String>,
+    ) -> PyResult<Self> {
+        let same_site = samesite
+            .map(|s| {
+                SameSite::from_str(&s).ok_or_else(|| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Invalid samesite value: {}",
+                        s
+                    ))
+                })
+            })
+            .transpose()?;
+
+        Ok(PyCookie {
+            cookie: Cookie {
+                name,
+                value,
+                domain,
+                path,
+                expires: None,
+                secure,
+                http_only: httponly,
+                same_site,
+            },
+        })
+    }
+
+    fn __str__(&self) -> String {
+        format!("Cookie({}={})", self.cookie.name, self.cookie.value)
+    }
+
+    /// The cookie's name
+    #[getter]
+    fn name(&self) -> String {
+        self.cookie.name.clone()
+    }
+
+    /// The cookie's value
+    #[getter]
+    fn value(&self) -> String {
+        self.cookie.value.clone()
+    }
+
+    /// The `Domain` attribute, if present
+    #[getter]
+    fn domain(&self) -> Option<String> {
+        self.cookie.domain.clone()
+    }
+
+    /// The `Path` attribute, if present
+    #[getter]
+    fn path(&self) -> Option<String> {
+        self.cookie.path.clone()
+    }
+
+    /// The `Expires` attribute, if present
+    #[getter]
+    fn expires(&self) -> Option<DateTime<Utc>> {
+        self.cookie.expires
+    }
+
+    /// Whether the `Secure` attribute is set
+    #[getter]
+    fn secure(&self) -> bool {
+        self.cookie.secure
+    }
+
+    /// Whether the `HttpOnly` attribute is set
+    #[getter]
+    fn httponly(&self) -> bool {
+        self.cookie.http_only
+    }
+
+    /// The `SameSite` attribute, if present
+    #[getter]
+    fn samesite(&self) -> Option<String> {
+        self.cookie.same_site.map(|s| s.as_str().to_string())
+    }
+}
+
+impl PyCookie {
+    pub fn new(cookie: Cookie) -> Self {
+        PyCookie { cookie }
+    }
+}
+
+#[pyclass(module = "proxad", name = "WsFrame", freelist = 64)]
+pub struct PyWsFrame {
+    pub frame: Option<WsFrame>,
+
+    #[pyo3(set)]
+    pub opcode: Option<Py<PyString>>,
+    #[pyo3(set)]
+    pub payload: Option<Py<PyBytes>>,
+    #[pyo3(set)]
+    pub fin: Option<bool>,
+}
+
+#[pymethods]
+impl PyWsFrame {
+    #[new]
+    pub fn py_new(opcode: Py<PyString>, payload: Py<PyBytes>, fin: bool) -> Self {
+        PyWsFrame {
+            frame: None,
+            opcode: Some(opcode),
+            payload: Some(payload),
+            fin: Some(fin),
+        }
+    }
+
+    fn __str__(self_: PyRef<'_, Self>, py: Python<'_>) -> PyResult<String> {
+        let opcode = if let Some(ref opcode) = self_.opcode {
+            Some(opcode.bind(py).to_str()?.to_string())
+        } else {
+            self_.frame.as_ref().map(|f| f.opcode.as_str().to_string())
+        };
+
+        let payload_len = self_
+            .payload
+            .as_ref()
+            .map(|b| b.bind(py).as_bytes().len())
+            .or_else(|| self_.frame.as_ref().map(|f| f.payload.len()))
+            .unwrap_or(0);
+
+        Ok(format!(
+            "WsFrame(opcode={}, payload.len={})",
+            opcode.as_deref().unwrap_or("<invalid>"),
+            payload_len
+        ))
+    }
+
+    /// Returns the opcode of this frame as a string (e.g. "text", "binary")
+    #[getter]
+    fn get_opcode(&mut self, py: Python<'_>) -> PyResult<Py<PyString>> {
+        cached_getter!(self, py, frame, opcode, {
+            <Py<PyString>>::from(PyString::new(py, frame.opcode.as_str()))
+        })
+    }
+
+    /// Returns the payload of this frame as bytes
+    #[getter]
+    fn get_payload(&mut self, py: Python<'_>) -> PyResult<Py<PyBytes>> {
+        cached_getter!(self, py, frame, payload, {
+            <Py<PyBytes>>::from(PyBytes::new(py, &frame.payload))
+        })
+    }
+
+    /// Returns whether this frame is the final fragment of its message
+    #[getter]
+    fn get_fin(&mut self) -> PyResult<bool> {
+        if let Some(fin) = self.fin {
+            return Ok(fin);
+        }
+
+        if let Some(ref frame) = self.frame {
+            self.fin = Some(frame.fin);
+            Ok(frame.fin)
+        } else {
+            Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "Invalid WsFrame object",
+            ))
+        }
+    }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.opcode)?;
+        visit.call(&self.payload)?;
+        Ok(())
+    }
+
+    fn __clear__(&mut self) {
+        self.opcode = None;
+        self.payload = None;
+    }
+}
+
+impl PyWsFrame {
+    pub fn new(frame: WsFrame) -> Self {
+        PyWsFrame {
+            frame: Some(frame),
+            opcode: None,
+            payload: None,
+            fin: None,
         }
     }
 }
@@ -534,11 +869,60 @@ impl PyUri {
     }
 }
 
+/// An outbound HTTP client for filters that need to make a side request
+/// (e.g. to an auth service or a honeypot-scoring endpoint) before
+/// deciding how to handle a flow. Backed by the process-wide pooled
+/// client in [`crate::client`], sharing its `max_body`/timeout limits.
+#[pyclass(module = "proxad", name = "Client", freelist = 8)]
+pub struct PyClient;
+
+#[pymethods]
+impl PyClient {
+    #[new]
+    fn py_new() -> Self {
+        PyClient
+    }
+
+    fn __str__(&self) -> String {
+        "Client()".to_string()
+    }
+
+    /// Sends `req` (an `HttpReq`, built the same way a filter's own
+    /// request argument is) and blocks until the response comes back,
+    /// releasing the GIL for the duration of the call so it doesn't stall
+    /// other Python threads.
+    fn request(&self, py: Python<'_>, req: HttpRequest) -> PyResult<HttpResponse> {
+        let Some(client) = crate::client::get() else {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Outbound HTTP client is not initialized",
+            ));
+        };
+
+        py.allow_threads(move || {
+            tokio::task::block_in_place(move || {
+                tokio::runtime::Handle::current().block_on(client.send(req))
+            })
+        })
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+    }
+}
+
+/// A sentinel a raw filter returns instead of `...` to signal that the
+/// client isn't just worth dropping, but banning outright: exposed to
+/// scripts as the singleton `proxad.BAN`.
+#[pyclass(module = "proxad", name = "Ban", frozen)]
+pub struct PyBan;
+
 pub fn register_proxad(module: &Bound<'_, PyModule>) -> PyResult<()> {
     module.add_class::<PyRawFlow>()?;
     module.add_class::<PyHttpFlow>()?;
     module.add_class::<PyHttpResp>()?;
     module.add_class::<PyHttpReq>()?;
+    module.add_class::<PyWsFrame>()?;
+    module.add_class::<PyCookie>()?;
+    module.add_class::<PyClient>()?;
     module.add_class::<PyUri>()?;
+    module.add_class::<PyBan>()?;
+    module.add("BAN", Py::new(module.py(), PyBan)?)?;
     Ok(())
 }
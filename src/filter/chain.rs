@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use futures_util::StreamExt;
+use inotify::{Inotify, WatchMask};
+use tokio::sync::RwLock;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::filter::{Filter, INOTIFY_DEBOUNCE_TIME, RawVerdict};
+use crate::flow::{HttpFlow, RawFlow};
+use crate::http::HttpRequest;
+
+/// An ordered chain of Python filter stages loaded from every `.py` script in
+/// a directory, sorted by file name. A chunk or flow event runs through each
+/// stage in turn, the way middleware layers run in a pipeline: whatever
+/// stage N rewrote the chunk/response to is what stage N+1 sees, since every
+/// stage mutates the same flow in place. The first stage to return a
+/// breaking verdict (`...`, or `proxad.BAN` for raw chunks) short-circuits
+/// the rest, the same as a single [`Filter`] would for itself — including
+/// each stage's own "returned the original object, so no change" shortcut.
+pub struct FilterChain {
+    dir: PathBuf,
+    filter_deadline: Duration,
+    stages: RwLock<Vec<Arc<Filter>>>,
+}
+
+impl FilterChain {
+    pub fn load_from_dir(dir: &Path, filter_deadline: Duration) -> anyhow::Result<FilterChain> {
+        Ok(FilterChain {
+            dir: dir.to_path_buf(),
+            filter_deadline,
+            stages: RwLock::new(Self::discover(dir, filter_deadline)?),
+        })
+    }
+
+    /// Loads every `.py` script directly inside `dir`, sorted by file name so
+    /// the chain order matches what an operator sees with `ls`.
+    fn discover(dir: &Path, filter_deadline: Duration) -> anyhow::Result<Vec<Arc<Filter>>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read filter directory {}", dir.display()))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "py"))
+            .collect();
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let path_str = path
+                    .to_str()
+                    .with_context(|| format!("Non UTF-8 filter script path {}", path.display()))?;
+                Filter::load_from_file(path_str, filter_deadline).map(Arc::new)
+            })
+            .collect()
+    }
+
+    pub async fn on_http_response(&self, flow: &mut HttpFlow) -> ControlFlow<()> {
+        for stage in self.stages.read().await.iter() {
+            if let ControlFlow::Break(()) = stage.on_http_response(flow).await {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    pub async fn on_http_open(&self, flow: &mut HttpFlow) -> ControlFlow<()> {
+        for stage in self.stages.read().await.iter() {
+            if let ControlFlow::Break(()) = stage.on_http_open(flow).await {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    pub async fn on_http_request_headers(
+        &self,
+        flow: &mut HttpFlow,
+        req: &HttpRequest,
+    ) -> ControlFlow<()> {
+        for stage in self.stages.read().await.iter() {
+            if let ControlFlow::Break(()) = stage.on_http_request_headers(flow, req).await {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    pub async fn on_http_request(&self, flow: &mut HttpFlow) -> ControlFlow<()> {
+        for stage in self.stages.read().await.iter() {
+            if let ControlFlow::Break(()) = stage.on_http_request(flow).await {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    pub async fn on_ws_client_frame(&self, flow: &mut HttpFlow) -> ControlFlow<()> {
+        for stage in self.stages.read().await.iter() {
+            if let ControlFlow::Break(()) = stage.on_ws_client_frame(flow).await {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    pub async fn on_ws_server_frame(&self, flow: &mut HttpFlow) -> ControlFlow<()> {
+        for stage in self.stages.read().await.iter() {
+            if let ControlFlow::Break(()) = stage.on_ws_server_frame(flow).await {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    pub async fn on_raw_open(&self, flow: &mut RawFlow) -> ControlFlow<()> {
+        for stage in self.stages.read().await.iter() {
+            if let ControlFlow::Break(()) = stage.on_raw_open(flow).await {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Runs every stage's `http_close`, in order, regardless of whether an
+    /// earlier stage broke the flow — close hooks always see the whole
+    /// chain, unlike the other hooks which short-circuit on `Break`.
+    pub async fn on_http_close(&self, flow: &HttpFlow) {
+        for stage in self.stages.read().await.iter() {
+            stage.on_http_close(flow).await;
+        }
+    }
+
+    /// Runs every stage's `raw_close`, in order. See
+    /// [`FilterChain::on_http_close`].
+    pub async fn on_raw_close(&self, flow: &RawFlow) {
+        for stage in self.stages.read().await.iter() {
+            stage.on_raw_close(flow).await;
+        }
+    }
+
+    pub async fn on_raw_client(&self, flow: &mut RawFlow) -> RawVerdict {
+        for stage in self.stages.read().await.iter() {
+            match stage.on_raw_client(flow).await {
+                RawVerdict::Pass => continue,
+                verdict => return verdict,
+            }
+        }
+        RawVerdict::Pass
+    }
+
+    pub async fn on_raw_server(&self, flow: &mut RawFlow) -> RawVerdict {
+        for stage in self.stages.read().await.iter() {
+            match stage.on_raw_server(flow).await {
+                RawVerdict::Pass => continue,
+                verdict => return verdict,
+            }
+        }
+        RawVerdict::Pass
+    }
+
+    /// Watches the chain's directory for changes: a modified script reloads
+    /// only the stage with that basename, and a newly created `.py` script
+    /// is loaded and spliced into the chain at its sorted position, both
+    /// without disturbing the other stages.
+    pub async fn spawn_watcher(self: Arc<Self>) -> anyhow::Result<()> {
+        let inotify = Inotify::init().context("Failed to initialize inotify")?;
+
+        inotify
+            .watches()
+            .add(&self.dir, WatchMask::MODIFY | WatchMask::CREATE)
+            .with_context(|| format!("Failed to watch directory {}", self.dir.display()))?;
+
+        let chain = self.clone();
+        tokio::spawn(async move {
+            let mut buffer = [0; 1024];
+            let mut stream = match inotify.into_event_stream(&mut buffer) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!("Failed to start filter chain watcher: {}", e);
+                    return;
+                }
+            };
+
+            let mut pending: HashSet<OsString> = HashSet::new();
+            loop {
+                tokio::select! {
+                    maybe_event = stream.next() => {
+                        match maybe_event {
+                            Some(Ok(event)) => {
+                                if let Some(name) = event.name {
+                                    if Path::new(&name).extension().is_some_and(|ext| ext == "py") {
+                                        info!("Detected change to filter script {}", name.to_string_lossy());
+                                        pending.insert(name);
+                                    }
+                                }
+                            }
+                            Some(Ok(_)) => (),
+                            Some(Err(e)) => warn!("Inotify error: {}", e),
+                            None => {
+                                warn!("Stopping the filter chain watcher");
+                                break;
+                            }
+                        }
+                    }
+
+                    _ = async {
+                        if pending.is_empty() {
+                            futures::future::pending::<()>().await;
+                        } else {
+                            sleep(INOTIFY_DEBOUNCE_TIME).await;
+                        }
+                    }, if !pending.is_empty() => {
+                        for name in pending.drain() {
+                            chain.reload_stage(&name).await;
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Reloads the stage whose script basename is `name` in place, or loads
+    /// and inserts it as a brand new stage (in sorted position) if it wasn't
+    /// part of the chain yet.
+    async fn reload_stage(&self, name: &OsString) {
+        let path = self.dir.join(name);
+
+        let mut stages = self.stages.write().await;
+        let existing = stages.iter().find(|stage| {
+            Path::new(stage.script_path.to_str().unwrap_or_default()).file_name()
+                == Some(name.as_os_str())
+        });
+
+        if let Some(stage) = existing {
+            match stage.reload().await {
+                Ok(()) => info!("Reloaded filter stage {}", path.display()),
+                Err(e) => error!("Failed to reload filter stage {}: {}", path.display(), e),
+            }
+            return;
+        }
+
+        let Some(path_str) = path.to_str() else {
+            warn!("Non UTF-8 filter script path {}", path.display());
+            return;
+        };
+
+        match Filter::load_from_file(path_str, self.filter_deadline) {
+            Ok(filter) => {
+                info!("Loaded new filter stage {}", path.display());
+                stages.push(Arc::new(filter));
+                stages.sort_by(|a, b| a.script_path.cmp(&b.script_path));
+            }
+            Err(e) => warn!("Failed to load new filter stage {}: {}", path.display(), e),
+        }
+    }
+}
@@ -1,39 +1,108 @@
 pub mod api;
+pub mod chain;
 
-use anyhow::Context;
 use either::Either;
-use futures_util::StreamExt;
-use inotify::{Inotify, WatchMask};
-use pyo3::ffi::c_str;
+use pyo3::exceptions::PyTimeoutError;
+use pyo3::ffi::{self, c_str};
 use pyo3::types::{PyBytes, PyDict, PyEllipsis, PyList, PyModule, PyString};
 use pyo3::{IntoPyObjectExt, intern, prelude::*};
+use std::cell::Cell;
 use std::ffi::{CStr, CString};
 use std::fs;
 use std::ops::ControlFlow;
-use std::path::PathBuf;
-use std::sync::Arc;
-use std::time::Duration;
+use std::os::raw::c_int;
+use std::ptr;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
-use tokio::time::sleep;
-use tracing::{debug, error, info, trace, warn};
+use tracing::{debug, info, trace, warn};
 
-use crate::filter::api::{PyHttpFlow, PyHttpResponse, PyRawFlow};
+use crate::filter::api::{PyBan, PyHttpFlow, PyHttpResp, PyRawFlow, PyWsFrame};
 use crate::flow::history::RawHistory;
 use crate::flow::{HttpFlow, RawFlow};
-use crate::http::HttpResponse;
+use crate::http::{HttpRequest, HttpResponse};
+use crate::ws::WsFrame;
 
 const INOTIFY_DEBOUNCE_TIME: Duration = Duration::from_secs(2);
 
 const HTTP_FILTER_FUNC: &str = "http_filter";
 const HTTP_OPEN_FUNC: &str = "http_open";
+const HTTP_REQUEST_HEADERS_FUNC: &str = "request_headers_filter";
+const HTTP_REQUEST_FUNC: &str = "request_filter";
+const HTTP_CLOSE_FUNC: &str = "http_close";
 
 const RAW_CLIENT_FUNC: &str = "client_raw_filter";
 const RAW_SERVER_FUNC: &str = "server_raw_filter";
 const RAW_OPEN_FUNC: &str = "raw_open";
+const RAW_CLOSE_FUNC: &str = "raw_close";
+
+const WS_CLIENT_FUNC: &str = "client_ws_filter";
+const WS_SERVER_FUNC: &str = "server_ws_filter";
+
+/// What a raw chunk filter decided about a client/server chunk.
+/// [`Filter::on_raw_client`]/[`Filter::on_raw_server`] already apply a
+/// `Replace` verdict to the flow's history themselves (there's no history
+/// left to hand back to the caller), so only the verdicts the read/write
+/// loop itself needs to act on are exposed here.
+pub enum RawVerdict {
+    /// Forward the chunk (possibly already rewritten in place) as normal.
+    Pass,
+    /// Tear down the flow without forwarding the chunk.
+    Drop,
+    /// Tear down the flow and signal the ban subsystem immediately.
+    Ban,
+}
+
+/// The raw decode of a Python raw-filter return value, before `Replace` has
+/// been applied to history.
+enum ChunkVerdict {
+    Pass,
+    Replace(Vec<u8>),
+    Drop,
+    Ban,
+}
+
+thread_local! {
+    /// The deadline the currently-running filter call must finish by, read
+    /// by [`trace_deadline`] on (almost) every bytecode line it executes.
+    static FILTER_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// A `sys.settrace`-style hook installed for the duration of a single filter
+/// call: once [`FILTER_DEADLINE`] has passed, it raises `TimeoutError` into
+/// the running Python frame instead of letting it keep going, so a runaway
+/// *Python-level* loop in one script can't stall the tokio worker forever.
+///
+/// This only fires on call/line/return events, which CPython only emits
+/// between bytecode instructions — it can't interrupt something already
+/// running in C. A catastrophically-backtracking `re` match, in particular,
+/// runs entirely inside the `re` module's C matcher and never yields a trace
+/// event, so this deadline does not bound it; that failure mode is still
+/// open.
+unsafe extern "C" fn trace_deadline(
+    _obj: *mut ffi::PyObject,
+    _frame: *mut ffi::PyFrameObject,
+    _what: c_int,
+    _arg: *mut ffi::PyObject,
+) -> c_int {
+    let expired = FILTER_DEADLINE.with(|deadline| deadline.get().is_some_and(|at| Instant::now() >= at));
+
+    if !expired {
+        return 0;
+    }
+
+    unsafe {
+        ffi::PyErr_SetString(
+            ffi::PyExc_TimeoutError,
+            c_str!("Filter execution deadline exceeded").as_ptr(),
+        );
+    }
+    -1
+}
 
 #[derive(Debug)]
 pub struct Filter {
     pub script_path: CString,
+    filter_deadline: Duration,
     inner: RwLock<FilterModule>,
 }
 
@@ -42,9 +111,15 @@ struct FilterModule {
     module: Py<PyModule>,
     http_filter: Option<Py<PyAny>>,
     http_open: Option<Py<PyAny>>,
+    http_request_headers: Option<Py<PyAny>>,
+    http_request: Option<Py<PyAny>>,
+    http_close: Option<Py<PyAny>>,
     raw_client: Option<Py<PyAny>>,
     raw_server: Option<Py<PyAny>>,
     raw_open: Option<Py<PyAny>>,
+    raw_close: Option<Py<PyAny>>,
+    ws_client: Option<Py<PyAny>>,
+    ws_server: Option<Py<PyAny>>,
 }
 
 impl Filter {
@@ -67,16 +142,63 @@ impl Filter {
         })
     }
 
-    pub fn load_from_file(path: &str) -> anyhow::Result<Filter> {
+    pub fn load_from_file(path: &str, filter_deadline: Duration) -> anyhow::Result<Filter> {
         let path = CString::new(path)?;
         let module = Self::load_module(&path)?;
 
         Ok(Filter {
             script_path: path,
+            filter_deadline,
             inner: RwLock::new(module),
         })
     }
 
+    /// Arms the execution-deadline trace for the call about to be made
+    /// through `self.inner`'s Python functions.
+    fn arm_deadline(&self) {
+        let deadline = Instant::now() + self.filter_deadline;
+        FILTER_DEADLINE.with(|cell| cell.set(Some(deadline)));
+        unsafe { ffi::PyEval_SetTrace(Some(trace_deadline), ptr::null_mut()) };
+    }
+
+    /// Disarms the trace installed by [`Filter::arm_deadline`].
+    fn disarm_deadline() {
+        unsafe { ffi::PyEval_SetTrace(None, ptr::null_mut()) };
+        FILTER_DEADLINE.with(|cell| cell.set(None));
+    }
+
+    /// Whether `err` is the `TimeoutError` [`trace_deadline`] raises once a
+    /// filter call overruns its deadline, as opposed to any other Python
+    /// exception or conversion failure.
+    fn is_deadline_exceeded(err: &anyhow::Error) -> bool {
+        Python::with_gil(|py| {
+            err.downcast_ref::<PyErr>()
+                .is_some_and(|err| err.is_instance_of::<PyTimeoutError>(py))
+        })
+    }
+
+    /// If `obj` is awaitable (the hook was defined as `async def`), schedules
+    /// its coroutine on the tokio runtime and awaits it with the GIL
+    /// released, so a filter doing network-backed I/O doesn't stall the
+    /// worker driving it. Otherwise returns `obj` unchanged.
+    async fn resolve_maybe_coroutine(obj: Py<PyAny>) -> PyResult<Py<PyAny>> {
+        let future = Python::with_gil(|py| -> PyResult<_> {
+            let bound = obj.bind(py);
+            if bound.hasattr("__await__")? {
+                Ok(Some(pyo3_async_runtimes::tokio::into_future(
+                    bound.clone(),
+                )?))
+            } else {
+                Ok(None)
+            }
+        })?;
+
+        match future {
+            Some(future) => future.await,
+            None => Ok(obj),
+        }
+    }
+
     fn load_module(path: &CStr) -> anyhow::Result<FilterModule> {
         let code = CString::new(fs::read(path.to_str()?)?)?;
         Python::with_gil(|py| {
@@ -97,40 +219,64 @@ impl Filter {
                 module: module.into(),
                 http_filter: load_function(intern!(py, HTTP_FILTER_FUNC))?,
                 http_open: load_function(intern!(py, HTTP_OPEN_FUNC))?,
+                http_request_headers: load_function(intern!(py, HTTP_REQUEST_HEADERS_FUNC))?,
+                http_request: load_function(intern!(py, HTTP_REQUEST_FUNC))?,
+                http_close: load_function(intern!(py, HTTP_CLOSE_FUNC))?,
                 raw_client: load_function(intern!(py, RAW_CLIENT_FUNC))?,
                 raw_server: load_function(intern!(py, RAW_SERVER_FUNC))?,
                 raw_open: load_function(intern!(py, RAW_OPEN_FUNC))?,
+                raw_close: load_function(intern!(py, RAW_CLOSE_FUNC))?,
+                ws_client: load_function(intern!(py, WS_CLIENT_FUNC))?,
+                ws_server: load_function(intern!(py, WS_SERVER_FUNC))?,
             })
         })
     }
 
     pub async fn on_http_response(&self, flow: &mut HttpFlow) -> ControlFlow<()> {
         if let Some(ref func) = self.inner.read().await.http_filter {
-            let result: anyhow::Result<Either<Option<Py<PyHttpResponse>>, Py<PyEllipsis>>> =
-                Python::with_gil(|py| {
-                    let req = flow
-                        .history
-                        .requests
-                        .last()
-                        .map(|(req, _)| req)
-                        .cloned()
-                        .ok_or_else(|| anyhow::anyhow!("Where is the request?"))?
-                        .into_pyobject(py)?;
-
-                    let resp = flow
-                        .history
-                        .responses
-                        .last()
-                        .map(|(resp, _)| resp)
-                        .cloned()
-                        .ok_or_else(|| anyhow::anyhow!("Where is the response?"))?
-                        .into_pyobject(py)?;
-
-                    debug!("Running filter {} for flow {}", HTTP_FILTER_FUNC, flow.id);
-                    let args = (PyHttpFlow::new(flow.id), &req, &resp);
-                    let result = func.bind(py).call1(args)?;
-                    Ok(result.extract()?)
-                });
+            let called: PyResult<Py<PyAny>> = Python::with_gil(|py| {
+                let req = flow
+                    .history
+                    .requests
+                    .last()
+                    .map(|(req, _)| req)
+                    .cloned()
+                    .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("Where is the request?"))?
+                    .into_pyobject(py)?;
+
+                let resp = flow
+                    .history
+                    .responses
+                    .last()
+                    .map(|(resp, _)| resp)
+                    .cloned()
+                    .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("Where is the response?"))?
+                    .into_pyobject(py)?;
+
+                debug!("Running filter {} for flow {}", HTTP_FILTER_FUNC, flow.id);
+                let args = (
+                    PyHttpFlow::new(
+                        flow.id,
+                        flow.start,
+                        flow.history.requests.last().map(|(_, t)| *t),
+                        flow.history.responses.last().map(|(_, t)| *t),
+                        flow.stream_id,
+                        flow.peer_cert_chain.as_deref(),
+                    ),
+                    &req,
+                    &resp,
+                );
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                result.map(Bound::unbind)
+            });
+
+            let result: anyhow::Result<Either<Option<Py<PyHttpResp>>, Py<PyEllipsis>>> = async {
+                let resolved = Self::resolve_maybe_coroutine(called?).await?;
+                Ok(Python::with_gil(|py| resolved.extract(py))?)
+            }
+            .await;
 
             match result {
                 // Kill connection on ellipses
@@ -149,6 +295,13 @@ impl Filter {
                 }
                 // Do nothing on none
                 Ok(Either::Left(None)) => (),
+                Err(e) if Self::is_deadline_exceeded(&e) => {
+                    warn!(
+                        "Filter {} exceeded its execution deadline, killing flow {}",
+                        HTTP_FILTER_FUNC, flow.id
+                    );
+                    return ControlFlow::Break(());
+                }
                 Err(e) => warn!("Failed to run python filter: {}", e),
             };
         };
@@ -157,193 +310,556 @@ impl Filter {
 
     pub async fn on_http_open(&self, flow: &mut HttpFlow) -> ControlFlow<()> {
         if let Some(ref func) = self.inner.read().await.http_open {
-            let result: anyhow::Result<Option<Py<PyEllipsis>>> = Python::with_gil(|py| {
+            let called: PyResult<Py<PyAny>> = Python::with_gil(|py| {
                 let args = (PyHttpFlow::new(flow.id),);
                 debug!("Running filter {} on flow {}", HTTP_OPEN_FUNC, flow.id);
-                let result = func.bind(py).call1(args)?;
-                Ok(result.extract()?)
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                result.map(Bound::unbind)
             });
 
+            let result: anyhow::Result<Option<Py<PyEllipsis>>> = async {
+                let resolved = Self::resolve_maybe_coroutine(called?).await?;
+                Ok(Python::with_gil(|py| resolved.extract(py))?)
+            }
+            .await;
+
             match result {
                 // Kill connection on ellipses
                 Ok(Some(_)) => return ControlFlow::Break(()),
                 // Do nothing on none
                 Ok(None) => (),
+                Err(e) if Self::is_deadline_exceeded(&e) => {
+                    warn!(
+                        "Filter {} exceeded its execution deadline, killing flow {}",
+                        HTTP_OPEN_FUNC, flow.id
+                    );
+                    return ControlFlow::Break(());
+                }
                 Err(e) => warn!("Failed to run python filter: {}", e),
             }
         };
         ControlFlow::Continue(())
     }
 
-    fn apply_raw_chunk(
+    /// Runs before the client body is buffered, against the request's
+    /// headers and URI only (the body is still empty). Used to decide
+    /// whether an `Expect: 100-continue` request is worth accepting before
+    /// the proxy commits to reading a potentially huge body.
+    pub async fn on_http_request_headers(
         &self,
-        result: anyhow::Result<Either<Option<Py<PyBytes>>, Py<PyEllipsis>>>,
-        history: &mut RawHistory,
+        flow: &mut HttpFlow,
+        req: &HttpRequest,
     ) -> ControlFlow<()> {
-        match result {
-            // Kill connection on ellipses
-            Ok(Either::Right(_)) => return ControlFlow::Break(()),
-            // Replace last chunk on bytes
-            Ok(Either::Left(Some(bytes))) => {
-                Python::with_gil(|py| match bytes.extract::<&[u8]>(py) {
-                    Ok(bytes) => {
-                        trace!("Modified chunk: {:?}", String::from_utf8_lossy(&bytes));
-                        history.set_last_chunk(bytes);
-                    }
-                    Err(e) => warn!("Failed to convert bytes: {}", e),
-                });
+        if let Some(ref func) = self.inner.read().await.http_request_headers {
+            let called: PyResult<Py<PyAny>> = Python::with_gil(|py| {
+                let req = req.clone().into_pyobject(py)?;
+
+                debug!(
+                    "Running filter {} on flow {}",
+                    HTTP_REQUEST_HEADERS_FUNC, flow.id
+                );
+                let args = (
+                    PyHttpFlow::new(
+                        flow.id,
+                        flow.start,
+                        None,
+                        None,
+                        flow.stream_id,
+                        flow.peer_cert_chain.as_deref(),
+                    ),
+                    &req,
+                );
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                result.map(Bound::unbind)
+            });
+
+            let result: anyhow::Result<Either<Option<Py<PyHttpResp>>, Py<PyEllipsis>>> = async {
+                let resolved = Self::resolve_maybe_coroutine(called?).await?;
+                Ok(Python::with_gil(|py| resolved.extract(py))?)
             }
-            // Do nothing on none
-            Ok(Either::Left(None)) => (),
-            Err(e) => warn!("Failed to run python filter: {}", e),
+            .await;
+
+            match result {
+                // Kill the request on ellipses
+                Ok(Either::Right(_)) => return ControlFlow::Break(()),
+                // A returned response short-circuits the request with it
+                Ok(Either::Left(Some(resp))) => {
+                    Python::with_gil(|py| match resp.extract::<HttpResponse>(py) {
+                        Ok(resp) => {
+                            trace!("Short-circuit response: {:?}", resp);
+                            let len = resp.0.body().len();
+                            let coding = resp.1;
+                            flow.history.push_response(resp.0, len, coding);
+                        }
+                        Err(e) => warn!("Failed to convert response: {}", e),
+                    });
+                    return ControlFlow::Break(());
+                }
+                // Do nothing on none
+                Ok(Either::Left(None)) => (),
+                Err(e) if Self::is_deadline_exceeded(&e) => {
+                    warn!(
+                        "Filter {} exceeded its execution deadline, killing flow {}",
+                        HTTP_REQUEST_HEADERS_FUNC, flow.id
+                    );
+                    return ControlFlow::Break(());
+                }
+                Err(e) => warn!("Failed to run python filter: {}", e),
+            };
         };
         ControlFlow::Continue(())
     }
 
-    pub async fn on_raw_client(&self, flow: &mut RawFlow) -> ControlFlow<()> {
-        if let Some(ref func) = self.inner.read().await.raw_client {
-            let result: anyhow::Result<Either<Option<Py<PyBytes>>, Py<PyEllipsis>>> =
-                Python::with_gil(|py| {
-                    let bytes = PyBytes::new(py, flow.client_history.last_chunk());
-                    let args = (
-                        PyRawFlow::new(
-                            flow.id,
-                            &flow.client_history.bytes,
-                            &flow.server_history.bytes,
-                        ),
-                        &bytes,
+    /// Runs once the client body has been fully buffered, before the
+    /// request is forwarded upstream.
+    pub async fn on_http_request(&self, flow: &mut HttpFlow) -> ControlFlow<()> {
+        if let Some(ref func) = self.inner.read().await.http_request {
+            let called: PyResult<Py<PyAny>> = Python::with_gil(|py| {
+                let req = flow
+                    .history
+                    .requests
+                    .last()
+                    .map(|(req, _)| req)
+                    .cloned()
+                    .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("Where is the request?"))?
+                    .into_pyobject(py)?;
+
+                debug!("Running filter {} on flow {}", HTTP_REQUEST_FUNC, flow.id);
+                let args = (
+                    PyHttpFlow::new(
+                        flow.id,
+                        flow.start,
+                        None,
+                        None,
+                        flow.stream_id,
+                        flow.peer_cert_chain.as_deref(),
+                    ),
+                    &req,
+                );
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                result.map(Bound::unbind)
+            });
+
+            let result: anyhow::Result<Either<Option<Py<PyHttpResp>>, Py<PyEllipsis>>> = async {
+                let resolved = Self::resolve_maybe_coroutine(called?).await?;
+                Ok(Python::with_gil(|py| resolved.extract(py))?)
+            }
+            .await;
+
+            match result {
+                // Kill the request on ellipses
+                Ok(Either::Right(_)) => return ControlFlow::Break(()),
+                // A returned response short-circuits the request with it
+                Ok(Either::Left(Some(resp))) => {
+                    Python::with_gil(|py| match resp.extract::<HttpResponse>(py) {
+                        Ok(resp) => {
+                            trace!("Short-circuit response: {:?}", resp);
+                            let len = resp.0.body().len();
+                            let coding = resp.1;
+                            flow.history.push_response(resp.0, len, coding);
+                        }
+                        Err(e) => warn!("Failed to convert response: {}", e),
+                    });
+                    return ControlFlow::Break(());
+                }
+                // Do nothing on none
+                Ok(Either::Left(None)) => (),
+                Err(e) if Self::is_deadline_exceeded(&e) => {
+                    warn!(
+                        "Filter {} exceeded its execution deadline, killing flow {}",
+                        HTTP_REQUEST_FUNC, flow.id
+                    );
+                    return ControlFlow::Break(());
+                }
+                Err(e) => warn!("Failed to run python filter: {}", e),
+            };
+        };
+        ControlFlow::Continue(())
+    }
+
+    pub async fn on_ws_client_frame(&self, flow: &mut HttpFlow) -> ControlFlow<()> {
+        if let Some(ref func) = self.inner.read().await.ws_client {
+            let called: PyResult<Py<PyAny>> = Python::with_gil(|py| {
+                let frame = flow
+                    .history
+                    .ws_client
+                    .last()
+                    .map(|(frame, _)| frame)
+                    .cloned()
+                    .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("Where is the frame?"))?
+                    .into_pyobject(py)?;
+
+                debug!("Running filter {} on flow {}", WS_CLIENT_FUNC, flow.id);
+                let args = (
+                    PyHttpFlow::new(
+                        flow.id,
+                        flow.start,
+                        flow.history.requests.last().map(|(_, t)| *t),
+                        flow.history.responses.last().map(|(_, t)| *t),
+                        flow.stream_id,
+                        flow.peer_cert_chain.as_deref(),
+                    ),
+                    &frame,
+                );
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                result.map(Bound::unbind)
+            });
+
+            let result: anyhow::Result<Either<Option<Py<PyWsFrame>>, Py<PyEllipsis>>> = async {
+                let resolved = Self::resolve_maybe_coroutine(called?).await?;
+                Ok(Python::with_gil(|py| resolved.extract(py))?)
+            }
+            .await;
+
+            match result {
+                // Kill connection on ellipses
+                Ok(Either::Right(_)) => return ControlFlow::Break(()),
+                // Replace last frame on a returned WsFrame
+                Ok(Either::Left(Some(frame))) => {
+                    Python::with_gil(|py| match frame.extract::<WsFrame>(py) {
+                        Ok(frame) => {
+                            trace!("Modified client ws frame: {:?}", frame);
+                            if let Some((last, _)) = flow.history.ws_client.last_mut() {
+                                *last = frame;
+                            }
+                        }
+                        Err(e) => warn!("Failed to convert frame: {}", e),
+                    });
+                }
+                // Do nothing on none
+                Ok(Either::Left(None)) => (),
+                Err(e) if Self::is_deadline_exceeded(&e) => {
+                    warn!(
+                        "Filter {} exceeded its execution deadline, killing flow {}",
+                        WS_CLIENT_FUNC, flow.id
                     );
+                    return ControlFlow::Break(());
+                }
+                Err(e) => warn!("Failed to run python filter: {}", e),
+            };
+        };
+        ControlFlow::Continue(())
+    }
 
-                    debug!("Running filter {} on flow {}", RAW_CLIENT_FUNC, flow.id);
-                    let result = func.bind(py).call1(args)?;
+    pub async fn on_ws_server_frame(&self, flow: &mut HttpFlow) -> ControlFlow<()> {
+        if let Some(ref func) = self.inner.read().await.ws_server {
+            let called: PyResult<Py<PyAny>> = Python::with_gil(|py| {
+                let frame = flow
+                    .history
+                    .ws_server
+                    .last()
+                    .map(|(frame, _)| frame)
+                    .cloned()
+                    .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("Where is the frame?"))?
+                    .into_pyobject(py)?;
+
+                debug!("Running filter {} on flow {}", WS_SERVER_FUNC, flow.id);
+                let args = (
+                    PyHttpFlow::new(
+                        flow.id,
+                        flow.start,
+                        flow.history.requests.last().map(|(_, t)| *t),
+                        flow.history.responses.last().map(|(_, t)| *t),
+                        flow.stream_id,
+                        flow.peer_cert_chain.as_deref(),
+                    ),
+                    &frame,
+                );
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                result.map(Bound::unbind)
+            });
+
+            let result: anyhow::Result<Either<Option<Py<PyWsFrame>>, Py<PyEllipsis>>> = async {
+                let resolved = Self::resolve_maybe_coroutine(called?).await?;
+                Ok(Python::with_gil(|py| resolved.extract(py))?)
+            }
+            .await;
+
+            match result {
+                // Kill connection on ellipses
+                Ok(Either::Right(_)) => return ControlFlow::Break(()),
+                // Replace last frame on a returned WsFrame
+                Ok(Either::Left(Some(frame))) => {
+                    Python::with_gil(|py| match frame.extract::<WsFrame>(py) {
+                        Ok(frame) => {
+                            trace!("Modified server ws frame: {:?}", frame);
+                            if let Some((last, _)) = flow.history.ws_server.last_mut() {
+                                *last = frame;
+                            }
+                        }
+                        Err(e) => warn!("Failed to convert frame: {}", e),
+                    });
+                }
+                // Do nothing on none
+                Ok(Either::Left(None)) => (),
+                Err(e) if Self::is_deadline_exceeded(&e) => {
+                    warn!(
+                        "Filter {} exceeded its execution deadline, killing flow {}",
+                        WS_SERVER_FUNC, flow.id
+                    );
+                    return ControlFlow::Break(());
+                }
+                Err(e) => warn!("Failed to run python filter: {}", e),
+            };
+        };
+        ControlFlow::Continue(())
+    }
 
-                    if result.is(bytes) {
-                        trace!("Python returned the original response, ignoring");
-                        Ok(Either::Left(None))
-                    } else {
-                        Ok(result.extract()?)
-                    }
-                });
+    /// Decodes a raw filter's return value into a [`ChunkVerdict`]: the
+    /// chunk object itself (untouched) or `None` means `Pass`, `...` means
+    /// `Drop`, the `proxad.BAN` sentinel means `Ban`, and anything else is
+    /// extracted as the replacement bytes.
+    fn decode_chunk_verdict(
+        result: &Bound<'_, PyAny>,
+        original: &Bound<'_, PyBytes>,
+    ) -> PyResult<ChunkVerdict> {
+        if result.is(original) || result.is_none() {
+            Ok(ChunkVerdict::Pass)
+        } else if result.is_instance_of::<PyEllipsis>() {
+            Ok(ChunkVerdict::Drop)
+        } else if result.is_instance_of::<PyBan>() {
+            Ok(ChunkVerdict::Ban)
+        } else {
+            Ok(ChunkVerdict::Replace(result.extract()?))
+        }
+    }
+
+    fn apply_raw_chunk(
+        &self,
+        result: anyhow::Result<ChunkVerdict>,
+        history: &mut RawHistory,
+    ) -> RawVerdict {
+        match result {
+            Ok(ChunkVerdict::Drop) => RawVerdict::Drop,
+            Ok(ChunkVerdict::Ban) => RawVerdict::Ban,
+            Ok(ChunkVerdict::Replace(bytes)) => {
+                trace!("Modified chunk: {:?}", String::from_utf8_lossy(&bytes));
+                history.set_last_chunk(&bytes);
+                RawVerdict::Pass
+            }
+            Ok(ChunkVerdict::Pass) => RawVerdict::Pass,
+            Err(e) => {
+                warn!("Failed to run python filter: {}", e);
+                RawVerdict::Pass
+            }
+        }
+    }
+
+    pub async fn on_raw_client(&self, flow: &mut RawFlow) -> RawVerdict {
+        if let Some(ref func) = self.inner.read().await.raw_client {
+            let called: PyResult<(Py<PyAny>, Py<PyBytes>)> = Python::with_gil(|py| {
+                let bytes = PyBytes::new(py, flow.client_history.last_chunk());
+                let args = (
+                    PyRawFlow::new(
+                        flow.id,
+                        &flow.client_history.bytes,
+                        &flow.server_history.bytes,
+                        flow.peer_cert_chain.as_deref(),
+                        flow.quic_stream_id,
+                    ),
+                    &bytes,
+                );
+
+                debug!("Running filter {} on flow {}", RAW_CLIENT_FUNC, flow.id);
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                Ok((result?.unbind(), bytes.unbind()))
+            });
+
+            let result: anyhow::Result<ChunkVerdict> = async {
+                let (called, bytes) = called?;
+                let resolved = Self::resolve_maybe_coroutine(called).await?;
+                Ok(Python::with_gil(|py| {
+                    Self::decode_chunk_verdict(resolved.bind(py), bytes.bind(py))
+                })?)
+            }
+            .await;
+
+            let result = result.or_else(|e| {
+                if Self::is_deadline_exceeded(&e) {
+                    warn!(
+                        "Filter {} exceeded its execution deadline, dropping flow {}",
+                        RAW_CLIENT_FUNC, flow.id
+                    );
+                    Ok(ChunkVerdict::Drop)
+                } else {
+                    Err(e)
+                }
+            });
 
             self.apply_raw_chunk(result, &mut flow.client_history)
         } else {
-            ControlFlow::Continue(())
+            RawVerdict::Pass
         }
     }
 
-    pub async fn on_raw_server(&self, flow: &mut RawFlow) -> ControlFlow<()> {
+    pub async fn on_raw_server(&self, flow: &mut RawFlow) -> RawVerdict {
         if let Some(ref func) = self.inner.read().await.raw_server {
-            let result: anyhow::Result<Either<Option<Py<PyBytes>>, Py<PyEllipsis>>> =
-                Python::with_gil(|py| {
-                    let bytes = PyBytes::new(py, flow.server_history.last_chunk());
-                    let args = (
-                        PyRawFlow::new(
-                            flow.id,
-                            &flow.client_history.bytes,
-                            &flow.server_history.bytes,
-                        ),
-                        &bytes,
-                    );
+            let called: PyResult<(Py<PyAny>, Py<PyBytes>)> = Python::with_gil(|py| {
+                let bytes = PyBytes::new(py, flow.server_history.last_chunk());
+                let args = (
+                    PyRawFlow::new(
+                        flow.id,
+                        &flow.client_history.bytes,
+                        &flow.server_history.bytes,
+                        flow.peer_cert_chain.as_deref(),
+                        flow.quic_stream_id,
+                    ),
+                    &bytes,
+                );
+
+                debug!("Running filter {} on flow {}", RAW_SERVER_FUNC, flow.id);
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                Ok((result?.unbind(), bytes.unbind()))
+            });
 
-                    debug!("Running filter {} on flow {}", RAW_SERVER_FUNC, flow.id);
-                    let result = func.bind(py).call1(args)?;
+            let result: anyhow::Result<ChunkVerdict> = async {
+                let (called, bytes) = called?;
+                let resolved = Self::resolve_maybe_coroutine(called).await?;
+                Ok(Python::with_gil(|py| {
+                    Self::decode_chunk_verdict(resolved.bind(py), bytes.bind(py))
+                })?)
+            }
+            .await;
 
-                    if result.is(bytes) {
-                        trace!("Python returned the original response, ignoring");
-                        Ok(Either::Left(None))
-                    } else {
-                        Ok(result.extract()?)
-                    }
-                });
+            let result = result.or_else(|e| {
+                if Self::is_deadline_exceeded(&e) {
+                    warn!(
+                        "Filter {} exceeded its execution deadline, dropping flow {}",
+                        RAW_SERVER_FUNC, flow.id
+                    );
+                    Ok(ChunkVerdict::Drop)
+                } else {
+                    Err(e)
+                }
+            });
 
             self.apply_raw_chunk(result, &mut flow.server_history)
         } else {
-            ControlFlow::Continue(())
+            RawVerdict::Pass
         }
     }
 
     pub async fn on_raw_open(&self, flow: &mut RawFlow) -> ControlFlow<()> {
         if let Some(ref func) = self.inner.read().await.raw_open {
-            let result: anyhow::Result<Option<Py<PyEllipsis>>> = Python::with_gil(|py| {
-                let args = (PyRawFlow::new_empty(flow.id),);
+            let called: PyResult<Py<PyAny>> = Python::with_gil(|py| {
+                let args = (PyRawFlow::new_empty(flow.id, flow.quic_stream_id),);
                 debug!("Running filter {} on flow {}", RAW_OPEN_FUNC, flow.id);
-                let result = func.bind(py).call1(args)?;
-                Ok(result.extract()?)
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                result.map(Bound::unbind)
             });
 
+            let result: anyhow::Result<Option<Py<PyEllipsis>>> = async {
+                let resolved = Self::resolve_maybe_coroutine(called?).await?;
+                Ok(Python::with_gil(|py| resolved.extract(py))?)
+            }
+            .await;
+
             match result {
                 // Kill connection on ellipses
                 Ok(Some(_)) => return ControlFlow::Break(()),
                 // Do nothing on none
                 Ok(None) => (),
+                Err(e) if Self::is_deadline_exceeded(&e) => {
+                    warn!(
+                        "Filter {} exceeded its execution deadline, killing flow {}",
+                        RAW_OPEN_FUNC, flow.id
+                    );
+                    return ControlFlow::Break(());
+                }
                 Err(e) => warn!("Failed to run python filter: {}", e),
             }
         };
         ControlFlow::Continue(())
     }
 
-    pub async fn spawn_watcher(self: Arc<Self>) -> anyhow::Result<()> {
-        let inotify = Inotify::init().context("Failed to initialize inotify")?;
-
-        let path = self.script_path.to_str()?;
-
-        let parent = PathBuf::from(&path)
-            .parent()
-            .context("Script path has no parent directory")?
-            .to_path_buf();
-
-        let basename = PathBuf::from(&path)
-            .file_name()
-            .context("Failed to get file name")?
-            .to_os_string();
-
-        inotify
-            .watches()
-            .add(&parent, WatchMask::MODIFY)
-            .with_context(|| format!("Failed to watch directory {}", parent.to_string_lossy()))?;
-
-        let filter = self.clone();
-        tokio::spawn(async move {
-            let mut buffer = [0; 1024];
-            let mut stream = inotify.into_event_stream(&mut buffer).unwrap();
-            let path = filter.script_path.to_str().unwrap();
-
-            let mut recent = false;
-            loop {
-                tokio::select! {
-                    maybe_event = stream.next() => {
-                        match maybe_event {
-                            Some(Ok(event)) if event.name.as_deref().is_some_and(|name| name == basename) => {
-                                info!("Detected change to python filter {}", path);
-                                recent = true;
-                            }
-                            Some(Ok(_)) => (),
-                            Some(Err(e)) => warn!("Inotify error: {}", e),
-                            None => warn!("Stopping the filter watcher"),
-                        }
-                    }
-
-                    _ = async {
-                         if recent {
-                             sleep(INOTIFY_DEBOUNCE_TIME).await;
-                         } else {
-                             futures::future::pending::<()>().await;
-                         }
-                     }, if recent => {
-                         match Self::load_module(&filter.script_path) {
-                             Ok(module) => {
-                                 let mut guard = filter.inner.write().await;
-                                 *guard = module;
-                                 info!("Reloaded python filter script");
-                             }
-                             Err(e) => error!("Failed to reload python filter: {}", e),
-                         }
-                         recent = false;
-                     }
-                }
+    /// Runs once the HTTP connection this flow belonged to has finished,
+    /// whatever the reason (clean close, error, or an earlier hook's
+    /// `Break`). Callers must invoke this exactly once per flow regardless
+    /// of how the connection ended, so Python-side end-of-flow logic (final
+    /// logging, flag extraction, verdict export) always sees a close.
+    pub async fn on_http_close(&self, flow: &HttpFlow) {
+        if let Some(ref func) = self.inner.read().await.http_close {
+            let result: anyhow::Result<()> = Python::with_gil(|py| {
+                let requests = PyList::new(py, flow.history.requests.iter().map(|(req, _)| req.clone()))?;
+                let responses =
+                    PyList::new(py, flow.history.responses.iter().map(|(resp, _)| resp.clone()))?;
+
+                debug!("Running filter {} on flow {}", HTTP_CLOSE_FUNC, flow.id);
+                let args = (
+                    PyHttpFlow::new(
+                        flow.id,
+                        flow.start,
+                        flow.history.requests.last().map(|(_, t)| *t),
+                        flow.history.responses.last().map(|(_, t)| *t),
+                        flow.stream_id,
+                        flow.peer_cert_chain.as_deref(),
+                    ),
+                    requests,
+                    responses,
+                );
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                result?;
+                Ok(())
+            });
+
+            if let Err(e) = result {
+                warn!("Failed to run python filter: {}", e);
             }
-        });
+        }
+    }
+
+    /// Runs once the raw connection this flow belonged to has finished,
+    /// with the same one-call-per-flow guarantee as
+    /// [`Filter::on_http_close`].
+    pub async fn on_raw_close(&self, flow: &RawFlow) {
+        if let Some(ref func) = self.inner.read().await.raw_close {
+            let result: anyhow::Result<()> = Python::with_gil(|py| {
+                let args = (PyRawFlow::new(
+                    flow.id,
+                    &flow.client_history.bytes,
+                    &flow.server_history.bytes,
+                    flow.peer_cert_chain.as_deref(),
+                    flow.quic_stream_id,
+                ),);
+
+                debug!("Running filter {} on flow {}", RAW_CLOSE_FUNC, flow.id);
+                self.arm_deadline();
+                let result = func.bind(py).call1(args);
+                Self::disarm_deadline();
+                result?;
+                Ok(())
+            });
+
+            if let Err(e) = result {
+                warn!("Failed to run python filter: {}", e);
+            }
+        }
+    }
 
+    /// Reloads this stage's Python module from its own `script_path`,
+    /// swapping in the freshly parsed functions. Used by
+    /// [`chain::FilterChain`]'s directory watcher.
+    pub(crate) async fn reload(&self) -> anyhow::Result<()> {
+        let module = Self::load_module(&self.script_path)?;
+        *self.inner.write().await = module;
         Ok(())
     }
 }
@@ -3,11 +3,19 @@ use chrono::{DateTime, Utc};
 use hyper::{Request, Response};
 use std::ops::Range;
 
+use crate::compress::ContentCoding;
 use crate::http::{HttpRequest, HttpResponse};
+use crate::ws::WsFrame;
 
 pub struct HttpHistory {
     pub requests: Vec<(HttpRequest, DateTime<Utc>)>,
     pub responses: Vec<(HttpResponse, DateTime<Utc>)>,
+
+    /// Frames seen after a WebSocket upgrade, kept separately from the
+    /// buffered request/response pair that negotiated it.
+    pub ws_client: Vec<(WsFrame, DateTime<Utc>)>,
+    pub ws_server: Vec<(WsFrame, DateTime<Utc>)>,
+
     pub client_size: usize,
     pub server_size: usize,
     pub client_max: usize,
@@ -19,6 +27,8 @@ impl HttpHistory {
         HttpHistory {
             requests: vec![],
             responses: vec![],
+            ws_client: vec![],
+            ws_server: vec![],
             client_size: 0,
             server_size: 0,
             client_max,
@@ -26,22 +36,52 @@ impl HttpHistory {
         }
     }
 
-    pub fn push_request(&mut self, req: Request<Bytes>, len: usize) -> bool {
+    pub fn push_request(
+        &mut self,
+        req: Request<Bytes>,
+        len: usize,
+        coding: Option<ContentCoding>,
+    ) -> bool {
+        if len + self.client_size > self.client_max {
+            false
+        } else {
+            self.client_size += len;
+            self.requests.push((HttpRequest(req, coding), Utc::now()));
+            true
+        }
+    }
+
+    pub fn push_response(
+        &mut self,
+        resp: Response<Bytes>,
+        len: usize,
+        coding: Option<ContentCoding>,
+    ) -> bool {
+        if len + self.server_size > self.server_max {
+            false
+        } else {
+            self.server_size += len;
+            self.responses.push((HttpResponse(resp, coding), Utc::now()));
+            true
+        }
+    }
+
+    pub fn push_ws_client(&mut self, frame: WsFrame, len: usize) -> bool {
         if len + self.client_size > self.client_max {
             false
         } else {
             self.client_size += len;
-            self.requests.push((HttpRequest(req), Utc::now()));
+            self.ws_client.push((frame, Utc::now()));
             true
         }
     }
 
-    pub fn push_response(&mut self, resp: Response<Bytes>, len: usize) -> bool {
+    pub fn push_ws_server(&mut self, frame: WsFrame, len: usize) -> bool {
         if len + self.server_size > self.server_max {
             false
         } else {
             self.server_size += len;
-            self.responses.push((HttpResponse(resp), Utc::now()));
+            self.ws_server.push((frame, Utc::now()));
             true
         }
     }
@@ -57,6 +97,16 @@ pub struct RawHistory {
     pub bytes: Vec<u8>,
     pub chunks: Vec<RawChunk>,
     pub max_size: usize,
+
+    /// Absolute offset of `bytes[0]`. `RawChunk::range` is always expressed
+    /// in this absolute space, so it stays meaningful across compaction —
+    /// translate with [`RawHistory::local_range`] before indexing `bytes`.
+    pub base_offset: usize,
+
+    /// Absolute offset up to which chunks have already been handed off to
+    /// the dumper. Only reported in debug logs: the final dump at flow close
+    /// doesn't re-emit those chunks because compaction already removed them.
+    pub flushed: usize,
 }
 
 impl RawHistory {
@@ -65,16 +115,28 @@ impl RawHistory {
             bytes: vec![],
             chunks: vec![],
             max_size,
+            base_offset: 0,
+            flushed: 0,
         }
     }
 
+    fn local_range(&self, range: &Range<usize>) -> Range<usize> {
+        (range.start - self.base_offset)..(range.end - self.base_offset)
+    }
+
+    /// Returns the bytes a chunk refers to, translating its absolute range
+    /// into the current in-memory window.
+    pub fn chunk_bytes(&self, chunk: &RawChunk) -> &[u8] {
+        &self.bytes[self.local_range(&chunk.range)]
+    }
+
     pub fn last_chunk(&self) -> &[u8] {
         let range = self
             .chunks
             .last()
             .map(|chunk| chunk.range.clone())
-            .unwrap_or(0..0);
-        &self.bytes[range]
+            .unwrap_or(self.base_offset..self.base_offset);
+        &self.bytes[self.local_range(&range)]
     }
 
     pub fn last_timestamp(&self) -> DateTime<Utc> {
@@ -87,22 +149,83 @@ impl RawHistory {
     pub fn set_last_chunk(&mut self, bytes: &[u8]) {
         match self.chunks.pop() {
             Some(RawChunk { range, timestamp }) => {
-                let start = range.start;
-                self.bytes.truncate(start);
+                let local_start = range.start - self.base_offset;
+                self.bytes.truncate(local_start);
 
                 self.bytes.extend_from_slice(bytes);
                 self.chunks.push(RawChunk {
-                    range: start..start + bytes.len(),
+                    range: range.start..range.start + bytes.len(),
                     timestamp,
                 });
             }
             None => {
+                let start = self.base_offset + self.bytes.len();
                 self.bytes.extend_from_slice(bytes);
                 self.chunks.push(RawChunk {
-                    range: 0..bytes.len(),
+                    range: start..start + bytes.len(),
                     timestamp: Utc::now(),
                 });
             }
         }
     }
+
+    /// Whether the in-memory window has crossed `max_size` and should be
+    /// compacted via [`RawHistory::compact`].
+    pub fn should_compact(&self) -> bool {
+        self.bytes.len() >= self.max_size
+    }
+
+    /// Drains every chunk except the most recent one (which a filter may
+    /// still want to inspect or mutate) from the in-memory window, sliding
+    /// `base_offset` forward so `bytes` only ever holds the current window.
+    /// Returns the drained chunks together with their bytes, for the caller
+    /// to forward to the dumper as a streaming, incremental update.
+    ///
+    /// This always shrinks `bytes`, even if the caller fails to forward the
+    /// result — that's what bounds memory use on long-lived flows.
+    pub fn compact(&mut self) -> Vec<(RawChunk, Vec<u8>)> {
+        if self.chunks.len() <= 1 {
+            return vec![];
+        }
+
+        let keep_from = self.chunks.len() - 1;
+        let drained: Vec<RawChunk> = self.chunks.drain(..keep_from).collect();
+
+        let drained: Vec<(RawChunk, Vec<u8>)> = drained
+            .into_iter()
+            .map(|chunk| {
+                let bytes = self.bytes[self.local_range(&chunk.range)].to_vec();
+                (chunk, bytes)
+            })
+            .collect();
+
+        let cut = drained
+            .last()
+            .map(|(chunk, _)| chunk.range.end)
+            .unwrap_or(self.base_offset);
+        self.bytes.drain(..cut - self.base_offset);
+        self.base_offset = cut;
+
+        drained
+    }
+
+    /// Marks `upto` (an absolute offset) as successfully handed to the
+    /// dumper.
+    pub fn mark_flushed(&mut self, upto: usize) {
+        self.flushed = self.flushed.max(upto);
+    }
+
+    /// Returns the resident bytes covering `[start, start + len)`, both
+    /// absolute offsets in the same space as `RawChunk::range`, clamped to
+    /// whatever `compact` hasn't already evicted. Lets a caller tail a
+    /// growing flow the way an HTTP Range client tails a growing log: fetch
+    /// the newest bytes with `read_range(total_len - n, n)`, then poll for
+    /// growth using `base_offset + bytes.len()` (the current total length)
+    /// as the next cursor.
+    pub fn read_range(&self, start: usize, len: usize) -> &[u8] {
+        let total = self.base_offset + self.bytes.len();
+        let start = start.clamp(self.base_offset, total);
+        let end = start.saturating_add(len).min(total);
+        &self.bytes[self.local_range(&(start..end))]
+    }
 }
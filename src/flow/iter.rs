@@ -32,9 +32,9 @@ impl<'a> Iterator for FlowIterator<'a> {
         match self {
             FlowIterator::Raw(raw) => raw.next().map(|(addr, chunk)| {
                 let bytes = if addr == raw.flow.client_addr {
-                    Cow::Borrowed(&raw.flow.client_history.bytes[chunk.range.clone()])
+                    Cow::Borrowed(raw.flow.client_history.chunk_bytes(&chunk))
                 } else {
-                    Cow::Borrowed(&raw.flow.server_history.bytes[chunk.range.clone()])
+                    Cow::Borrowed(raw.flow.server_history.chunk_bytes(&chunk))
                 };
                 (addr, chunk.timestamp, bytes)
             }),
@@ -2,9 +2,11 @@ pub mod history;
 pub mod iter;
 
 use chrono::{DateTime, Utc};
+use rustls::pki_types::CertificateDer;
 use std::net::SocketAddr;
 use uuid::Uuid;
 
+use crate::config::DumpProtocol;
 use crate::flow::history::{HttpHistory, RawHistory};
 
 #[enum_dispatch::enum_dispatch(IsFlow)]
@@ -27,6 +29,24 @@ pub struct HttpFlow {
     pub client_addr: SocketAddr,
     pub server_addr: SocketAddr,
     pub history: HttpHistory,
+
+    /// HTTP/2 stream this flow's request/response pair belongs to within
+    /// its connection. Always 0 today, and chunk3-4 ("HTTP/2 flow parsing
+    /// and per-stream filtering") is NOT implemented by this field — there
+    /// is no h2 preface/ALPN demux, no per-stream `http_filter`/`http_open`,
+    /// no `RST_STREAM`. That request is still open.
+    ///
+    /// What exists today is a narrower, separate fix: `negotiated_alpn ==
+    /// "h2"` makes `Proxy::handle_accepted_tcp` refuse the connection
+    /// outright rather than silently misparsing an h2 client as HTTP/1.1.
+    /// This field is just reserved plumbing for whoever picks chunk3-4 back
+    /// up — nothing in this tree populates it with a real stream id yet.
+    pub stream_id: u32,
+
+    /// Certificate chain the client presented during the TLS handshake,
+    /// when the service has `tls_client_auth` enabled. `None` for plaintext
+    /// flows or when client auth isn't required.
+    pub peer_cert_chain: Option<Vec<CertificateDer<'static>>>,
 }
 
 pub struct RawFlow {
@@ -36,6 +56,20 @@ pub struct RawFlow {
     pub server_addr: SocketAddr,
     pub client_history: RawHistory,
     pub server_history: RawHistory,
+
+    /// See [`HttpFlow::peer_cert_chain`].
+    pub peer_cert_chain: Option<Vec<CertificateDer<'static>>>,
+
+    /// Local sequence number of the QUIC stream this flow was synthesized
+    /// for, when the service's `transport` is `quic` (see `proxy::quic`).
+    /// `None` for flows proxied over a plain TCP connection, which has no
+    /// notion of multiple streams sharing one flow.
+    pub quic_stream_id: Option<u64>,
+
+    /// What this flow's backend actually speaks, i.e. how
+    /// [`crate::proxy::dumper::Dumper`] should reconstruct it into a pcap.
+    /// See [`crate::config::Config::dump_protocol`].
+    pub protocol: DumpProtocol,
 }
 
 impl HttpFlow {
@@ -44,6 +78,7 @@ impl HttpFlow {
         client_max_history: usize,
         server_addr: SocketAddr,
         server_max_history: usize,
+        peer_cert_chain: Option<Vec<CertificateDer<'static>>>,
     ) -> HttpFlow {
         HttpFlow {
             id: Uuid::new_v4(),
@@ -51,6 +86,8 @@ impl HttpFlow {
             client_addr,
             server_addr,
             history: HttpHistory::new(client_max_history, server_max_history),
+            stream_id: 0,
+            peer_cert_chain,
         }
     }
 }
@@ -79,6 +116,8 @@ impl RawFlow {
         client_max_history: usize,
         server_addr: SocketAddr,
         server_max_history: usize,
+        peer_cert_chain: Option<Vec<CertificateDer<'static>>>,
+        protocol: DumpProtocol,
     ) -> RawFlow {
         RawFlow {
             id: Uuid::new_v4(),
@@ -87,6 +126,9 @@ impl RawFlow {
             server_addr,
             client_history: RawHistory::new(client_max_history),
             server_history: RawHistory::new(server_max_history),
+            peer_cert_chain,
+            quic_stream_id: None,
+            protocol,
         }
     }
 }
@@ -1,15 +1,19 @@
 use bytes::Bytes;
-use http::header::{CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::header::{CONTENT_ENCODING, CONTENT_LENGTH, COOKIE, SET_COOKIE, TRANSFER_ENCODING};
 use http::{HeaderName, HeaderValue, StatusCode};
 use http_body_util::combinators::BoxBody;
 use hyper::{Request, Response};
 use hyper_util::rt::TokioTimer;
-use pyo3::types::{PyAnyMethods, PyBytesMethods, PyDictMethods, PyStringMethods};
+use pyo3::types::{PyAnyMethods, PyBytesMethods, PyDictMethods, PyListMethods, PyStringMethods};
 use pyo3::{Bound, FromPyObject, IntoPyObject, Py, PyAny, PyErr, PyResult, Python};
+use std::collections::HashMap;
 use std::{io::Write, time::Duration};
+use tracing::warn;
 
+use crate::compress::{self, ContentCoding};
 use crate::config::Config;
-use crate::filter::api::{PyHttpReq, PyHttpResp};
+use crate::cookie;
+use crate::filter::api::{PyCookie, PyHttpReq, PyHttpResp};
 
 pub type BytesBody = BoxBody<Bytes, hyper::Error>;
 
@@ -23,6 +27,7 @@ pub struct HttpConfig {
     pub date_header: bool,
     pub max_body: u64,
     pub client_timeout: Duration,
+    pub client_body_timeout: Duration,
 }
 
 impl HttpConfig {
@@ -33,6 +38,7 @@ impl HttpConfig {
             date_header: config.http_date_header,
             max_body: config.http_max_body.as_u64(),
             client_timeout: config.client_timeout,
+            client_body_timeout: config.client_body_timeout,
         })
     }
 
@@ -55,12 +61,42 @@ impl HttpConfig {
     }
 }
 
+/// An HTTP response, paired with the `Content-Encoding` it was received
+/// under (if any). The body stored in `Response<Bytes>` is always
+/// plaintext: [`HttpResponse::into_wire`] re-applies the recorded codec when
+/// the response needs to hit the wire again.
 #[derive(Debug, Clone)]
-pub struct HttpResponse(pub Response<Bytes>);
+pub struct HttpResponse(pub Response<Bytes>, pub Option<ContentCoding>);
 
 impl HttpResponse {
+    /// Rebuilds the response ready for the wire: re-compresses the body
+    /// with the codec it arrived with, unless the filter dropped
+    /// `Content-Encoding` (in which case the plaintext body is kept as-is).
+    /// Fixes up `Content-Length` to match whenever a codec was recorded.
+    pub fn into_wire(self) -> Response<Bytes> {
+        let HttpResponse(resp, coding) = self;
+        let Some(coding) = coding else {
+            return resp;
+        };
+
+        let (mut parts, body) = resp.into_parts();
+        let body = if parts.headers.contains_key(CONTENT_ENCODING) {
+            compress::encode(coding, &body).unwrap_or_else(|e| {
+                warn!("Failed to re-encode response body as {:?}: {}", coding, e);
+                body
+            })
+        } else {
+            body
+        };
+
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+        Response::from_parts(parts, body)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
-        let resp = &self.0;
+        let resp = self.clone().into_wire();
         let mut buf = Vec::new();
 
         write!(
@@ -82,12 +118,39 @@ impl HttpResponse {
     }
 }
 
+/// An HTTP request, paired with the `Content-Encoding` it was received
+/// under (if any). See [`HttpResponse`] for why the body is kept plaintext.
 #[derive(Debug, Clone)]
-pub struct HttpRequest(pub Request<Bytes>);
+pub struct HttpRequest(pub Request<Bytes>, pub Option<ContentCoding>);
 
 impl HttpRequest {
+    /// Rebuilds the request ready for the wire, re-compressing the body
+    /// with the codec it arrived with and fixing up `Content-Length` to
+    /// match. See [`HttpResponse::into_wire`].
+    pub fn into_wire(self) -> Request<Bytes> {
+        let HttpRequest(req, coding) = self;
+        let Some(coding) = coding else {
+            return req;
+        };
+
+        let (mut parts, body) = req.into_parts();
+        let body = if parts.headers.contains_key(CONTENT_ENCODING) {
+            compress::encode(coding, &body).unwrap_or_else(|e| {
+                warn!("Failed to re-encode request body as {:?}: {}", coding, e);
+                body
+            })
+        } else {
+            body
+        };
+
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+        Request::from_parts(parts, body)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
-        let req = &self.0;
+        let req = self.clone().into_wire();
         let mut buf = Vec::new();
 
         write!(
@@ -139,8 +202,9 @@ impl<'py> FromPyObject<'py> for HttpResponse {
         let inner = resp
             .resp
             .take()
-            .unwrap_or_else(|| HttpResponse(Response::default()));
+            .unwrap_or_else(|| HttpResponse(Response::default(), None));
 
+        let coding = inner.1;
         let (mut parts, old_body) = inner.0.into_parts();
 
         if let Some(headers) = resp.headers.take() {
@@ -172,6 +236,22 @@ impl<'py> FromPyObject<'py> for HttpResponse {
             }
         }
 
+        if let Some(cookies) = resp.cookies.take() {
+            parts.headers.remove(SET_COOKIE);
+
+            for cookie in cookies.bind(ob.py()).iter() {
+                let cookie: &Bound<'py, PyCookie> = cookie.downcast()?;
+                let value = cookie.borrow().cookie.to_header_value();
+                let hv = HeaderValue::from_str(&value).map_err(|e| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Invalid cookie value: {}",
+                        e
+                    ))
+                })?;
+                parts.headers.append(SET_COOKIE, hv);
+            }
+        }
+
         let body = if let Some(body) = resp.body.as_ref() {
             let bound = body.bind(ob.py());
             Bytes::copy_from_slice(&bound.as_bytes())
@@ -188,7 +268,7 @@ impl<'py> FromPyObject<'py> for HttpResponse {
             })?;
         }
 
-        Ok(HttpResponse(Response::from_parts(parts, body)))
+        Ok(HttpResponse(Response::from_parts(parts, body), coding))
     }
 }
 
@@ -211,8 +291,9 @@ impl<'py> FromPyObject<'py> for HttpRequest {
         let inner = req
             .req
             .take()
-            .unwrap_or_else(|| HttpRequest(Request::default()));
+            .unwrap_or_else(|| HttpRequest(Request::default(), None));
 
+        let coding = inner.1;
         let (mut parts, old_body) = inner.0.into_parts();
 
         if let Some(headers) = req.headers.take() {
@@ -244,6 +325,29 @@ impl<'py> FromPyObject<'py> for HttpRequest {
             }
         }
 
+        if let Some(cookies) = req.cookies.take() {
+            parts.headers.remove(COOKIE);
+
+            let mut map = HashMap::new();
+            for (k, v) in cookies.bind(ob.py()).iter() {
+                let k: String = k.extract()?;
+                let v: String = v.extract()?;
+                map.insert(k, v);
+            }
+
+            if !map.is_empty() {
+                let hv = HeaderValue::from_str(&cookie::format_cookie_header(&map)).map_err(
+                    |e| {
+                        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                            "Invalid cookie value: {}",
+                            e
+                        ))
+                    },
+                )?;
+                parts.headers.insert(COOKIE, hv);
+            }
+        }
+
         let body = if let Some(body) = req.body.as_ref() {
             let bound = body.bind(ob.py());
             Bytes::copy_from_slice(&bound.as_bytes())
@@ -263,6 +367,6 @@ impl<'py> FromPyObject<'py> for HttpRequest {
             parts.uri = bound.uri.clone();
         }
 
-        Ok(HttpRequest(Request::from_parts(parts, body)))
+        Ok(HttpRequest(Request::from_parts(parts, body), coding))
     }
 }
@@ -1,21 +1,29 @@
+mod ban;
+mod client;
+mod compress;
 mod config;
+mod cookie;
 mod filter;
 mod flow;
 mod http;
 mod proxy;
 mod service;
+mod shutdown;
 mod stream;
 mod tls;
+mod ws;
 
 use clap::Parser;
 use std::process::exit;
 use std::sync::Arc;
 use tokio;
+use tokio::select;
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::{EnvFilter, fmt};
 
 use crate::config::Config;
 use crate::filter::Filter;
+use crate::filter::chain::FilterChain;
 use crate::proxy::Proxy;
 use crate::service::Service;
 
@@ -31,6 +39,11 @@ struct Args {
 
     #[arg(short, long, default_value = "true")]
     watcher: bool,
+
+    /// Replays a flow previously captured via `record_path` against the
+    /// configured service, then exits instead of starting the proxy.
+    #[arg(long)]
+    replay: Option<String>,
 }
 
 #[tokio::main]
@@ -72,38 +85,49 @@ async fn main() {
         }
     };
 
-    if config.python_script.is_some() {
+    if let Some(ref path) = args.replay {
+        match proxy::replay_file(&service, std::path::Path::new(path)).await {
+            Ok(()) => info!("Replay of {} finished", path),
+            Err(e) => error!("Replay of {} failed: {}", path, e),
+        }
+
+        exit(0);
+    }
+
+    if config.filter_dir.is_some() {
         match Filter::load_api() {
             Ok(()) => debug!("Loaded api python module"),
             Err(e) => error!("Failed to load api python module: {}", e),
         }
+
+        client::init(config.server_max_history.as_u64(), config.server_timeout);
     }
 
-    let filter = config
-        .python_script
+    let chain = config
+        .filter_dir
         .as_ref()
-        .map(|path| Filter::load_from_file(&path))
+        .map(|dir| FilterChain::load_from_dir(dir, config.filter_deadline))
         .transpose();
 
-    match filter {
-        Ok(Some(filter)) => {
-            let filter = Arc::new(filter);
+    match chain {
+        Ok(Some(chain)) => {
+            let chain = Arc::new(chain);
             info!(
-                "Loaded python filter {}",
-                config.python_script.as_ref().unwrap()
+                "Loaded python filter chain from {}",
+                config.filter_dir.as_ref().unwrap().display()
             );
-            service.filter = Some(filter.clone());
+            service.filter = Some(chain.clone());
 
             if args.watcher {
-                match filter.spawn_watcher().await {
-                    Ok(_) => info!("Started watcher for python filter"),
-                    Err(e) => error!("Failed to start watcher for filter: {}", e),
+                match chain.spawn_watcher().await {
+                    Ok(_) => info!("Started watcher for filter chain"),
+                    Err(e) => error!("Failed to start watcher for filter chain: {}", e),
                 }
             }
         }
-        Ok(None) => debug!("No python filter loaded"),
+        Ok(None) => debug!("No python filter chain loaded"),
         Err(e) => {
-            error!("Failed to load python filter: {:?}", e);
+            error!("Failed to load python filter chain: {:?}", e);
             exit(1);
         }
     }
@@ -113,13 +137,13 @@ async fn main() {
         Err(e) => warn!("Failed to raise NOFILE limits: {}", e),
     }
 
-    let task = match Proxy::start(service, &config).await {
-        Ok(task) => {
+    let (proxy, task) = match Proxy::start(service, &config).await {
+        Ok(started) => {
             info!(
                 "Started proxying {}:{} -> {}:{}",
                 &config.client_ip, &config.client_port, &config.server_ip, &config.server_port
             );
-            task
+            started
         }
         Err(e) => {
             error!("Proxy failed to start: {}", e);
@@ -127,13 +151,37 @@ async fn main() {
         }
     };
 
-    match tokio::signal::ctrl_c().await {
-        Ok(()) => {
-            info!("Bye!");
-            exit(0)
+    #[cfg(unix)]
+    {
+        let mut sigterm =
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(sigterm) => sigterm,
+                Err(e) => {
+                    error!("Unable to listen for SIGTERM: {}", e);
+                    exit(1);
+                }
+            };
+
+        select! {
+            result = tokio::signal::ctrl_c() => if let Err(e) = result {
+                error!("Unable to listen for SIGINT: {}", e);
+            },
+            _ = sigterm.recv() => {}
         }
-        Err(e) => error!("Unable to listen for shutdown signal: {}", e),
     }
 
-    task.await.unwrap()
+    #[cfg(not(unix))]
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("Unable to listen for shutdown signal: {}", e);
+    }
+
+    info!(
+        "Shutting down, draining active flows for up to {:?}",
+        config.shutdown_grace
+    );
+    proxy.shutdown().await;
+
+    info!("Bye!");
+    task.await.unwrap();
+    exit(0)
 }
@@ -2,7 +2,8 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use rustls::ServerConfig;
-use tokio::net::TcpListener;
+use rustls::pki_types::CertificateDer;
+use tokio::net::{TcpListener, TcpStream};
 use tokio_rustls::TlsAcceptor;
 
 use crate::proxy::stream::ProxyStream;
@@ -25,13 +26,37 @@ impl Acceptor {
         })
     }
 
-    pub async fn accept(&self) -> anyhow::Result<(ProxyStream, SocketAddr)> {
-        let (stream, addr) = self.listener.accept().await?;
+    /// Accepts a raw TCP connection. Cheap and never blocks on anything but
+    /// the kernel's accept queue — the TLS handshake itself happens
+    /// separately, in [`Acceptor::handshake`]. How many handshakes may run
+    /// at once is capped by the caller's accept loop.
+    pub async fn accept_raw(&self) -> anyhow::Result<(TcpStream, SocketAddr)> {
+        Ok(self.listener.accept().await?)
+    }
+
+    /// TLS-wraps an already-accepted connection, if the service is
+    /// configured for it. The second element is the client's presented
+    /// certificate chain, only ever `Some` when `tls_client_auth` is on and
+    /// the peer actually sent one. The third is the ALPN protocol negotiated
+    /// with the client, to be mirrored into the upstream handshake so the
+    /// selected protocol is preserved end to end.
+    pub async fn handshake(
+        &self,
+        stream: TcpStream,
+    ) -> anyhow::Result<(
+        ProxyStream,
+        Option<Vec<CertificateDer<'static>>>,
+        Option<Vec<u8>>,
+    )> {
         if let Some(config) = self.tls_config.clone() {
             let acceptor = TlsAcceptor::from(config);
-            Ok((Box::pin(acceptor.accept(stream).await?), addr))
+            let stream = acceptor.accept(stream).await?;
+            let (_, session) = stream.get_ref();
+            let peer_cert_chain = session.peer_certificates().map(<[_]>::to_vec);
+            let negotiated_alpn = session.alpn_protocol().map(<[_]>::to_vec);
+            Ok((Box::pin(stream), peer_cert_chain, negotiated_alpn))
         } else {
-            Ok((Box::pin(stream), addr))
+            Ok((Box::pin(stream), None, None))
         }
     }
 }
@@ -1,39 +1,201 @@
+use hyper::client::conn::http1::SendRequest;
+use hyper_util::rt::TokioIo;
+use rustls::ClientConfig;
 use rustls::pki_types::ServerName;
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 use tokio_rustls::TlsConnector;
 
+use crate::config::{ProxyProtocol, RetryPolicy, UpstreamTls};
+use crate::http::{BytesBody, HttpConfig};
+use crate::proxy::proxy_header;
+use crate::proxy::retry::with_retry;
 use crate::proxy::stream::ProxyStream;
 use crate::service::Service;
 
+/// The background task driving a pooled HTTP/1.1 upstream connection.
+pub type HttpConnTask = JoinHandle<hyper::Result<()>>;
+
+/// A hyper client handle together with the task polling its connection,
+/// handed out by [`Connector::acquire_http`] and returned to the idle pool
+/// via [`Connector::release_http`].
+pub struct AcquiredConn {
+    pub sender: SendRequest<BytesBody>,
+    pub upstream: HttpConnTask,
+}
+
+struct PooledConn {
+    sender: SendRequest<BytesBody>,
+    upstream: HttpConnTask,
+    idle_since: Instant,
+}
+
 pub struct Connector {
     server_addr: SocketAddr,
     server_name: Option<ServerName<'static>>,
-    tls_connector: Option<TlsConnector>,
+    /// `None` when the service has no TLS at all, or when
+    /// [`crate::config::UpstreamTls::Terminate`] says to dial the backend in
+    /// plaintext after decrypting the client side.
+    tls_client_config: Option<Arc<ClientConfig>>,
+    proxy_protocol: ProxyProtocol,
+    retry_policy: RetryPolicy,
+    http_pool: Mutex<HashMap<SocketAddr, Vec<PooledConn>>>,
+    http_max_idle_per_host: usize,
+    http_idle_timeout: Duration,
 }
 
 impl Connector {
     pub async fn new(service: &Service) -> anyhow::Result<Connector> {
+        let reencrypt = matches!(service.upstream_tls, UpstreamTls::Reencrypt);
+
         Ok(Connector {
             server_addr: service.server_addr,
-            server_name: service
-                .tls_config
-                .as_ref()
+            server_name: reencrypt
+                .then(|| service.tls_config.as_ref())
+                .flatten()
                 .map(|_| ServerName::from(service.server_addr.ip())),
-            tls_connector: service
-                .tls_config
-                .as_ref()
-                .map(|config| TlsConnector::from(config.client_config.clone())),
+            tls_client_config: reencrypt
+                .then(|| service.tls_config.as_ref())
+                .flatten()
+                .map(|config| config.client_config.clone()),
+            proxy_protocol: service.proxy_protocol,
+            retry_policy: service.connect_retry,
+            http_pool: Mutex::new(HashMap::new()),
+            http_max_idle_per_host: service.http_max_idle_per_host,
+            http_idle_timeout: service.http_idle_timeout,
+        })
+    }
+
+    /// Hands out an idle, healthy pooled HTTP/1.1 connection if one is
+    /// available, otherwise connects and handshakes a new one.
+    pub async fn acquire_http(
+        &self,
+        client_addr: SocketAddr,
+        http: &HttpConfig,
+        negotiated_alpn: Option<&[u8]>,
+    ) -> anyhow::Result<AcquiredConn> {
+        if let Some(conn) = self.take_pooled().await {
+            return Ok(conn);
+        }
+
+        let stream = self.connect_once(client_addr, negotiated_alpn).await?;
+        let (sender, conn) = http.client_builder().handshake(TokioIo::new(stream)).await?;
+        let upstream = tokio::spawn(conn);
+
+        Ok(AcquiredConn { sender, upstream })
+    }
+
+    async fn take_pooled(&self) -> Option<AcquiredConn> {
+        let mut pool = self.http_pool.lock().await;
+        let conns = pool.get_mut(&self.server_addr)?;
+
+        while let Some(conn) = conns.pop() {
+            if conn.sender.is_closed() || conn.idle_since.elapsed() >= self.http_idle_timeout {
+                conn.upstream.abort();
+                continue;
+            }
+
+            return Some(AcquiredConn {
+                sender: conn.sender,
+                upstream: conn.upstream,
+            });
+        }
+
+        None
+    }
+
+    /// Returns a connection to the idle pool, unless `evict` is set or the
+    /// connection is no longer healthy (e.g. the upstream half-closed it) —
+    /// in which case its driving task is aborted instead.
+    pub async fn release_http(&self, conn: AcquiredConn, evict: bool) {
+        if evict || conn.sender.is_closed() {
+            conn.upstream.abort();
+            return;
+        }
+
+        let mut pool = self.http_pool.lock().await;
+        let conns = pool.entry(self.server_addr).or_default();
+
+        conns.retain(|conn| {
+            let keep = !conn.sender.is_closed() && conn.idle_since.elapsed() < self.http_idle_timeout;
+            if !keep {
+                conn.upstream.abort();
+            }
+            keep
+        });
+
+        if conns.len() >= self.http_max_idle_per_host {
+            conn.upstream.abort();
+        } else {
+            conns.push(PooledConn {
+                sender: conn.sender,
+                upstream: conn.upstream,
+                idle_since: Instant::now(),
+            });
+        }
+    }
+
+    /// Dials the upstream, threading `client_addr` through so a PROXY
+    /// protocol header (see [`ProxyProtocol`]) can carry the real client
+    /// identity to the backend instead of the proxy's own source address.
+    /// `negotiated_alpn`, when set, is mirrored into the upstream TLS
+    /// handshake instead of the service's configured ALPN preference list,
+    /// so the protocol the client negotiated on accept is preserved.
+    pub async fn connect(
+        &self,
+        client_addr: SocketAddr,
+        negotiated_alpn: Option<&[u8]>,
+    ) -> anyhow::Result<ProxyStream> {
+        with_retry(&self.retry_policy, "Upstream connect", || {
+            self.connect_once(client_addr, negotiated_alpn)
         })
+        .await
     }
 
-    pub async fn connect(&self) -> anyhow::Result<ProxyStream> {
-        let stream = TcpStream::connect(self.server_addr).await?;
-        if let Some(ref connector) = self.tls_connector {
+    /// Connects once and, if `proxy_protocol` is enabled, writes the PROXY
+    /// protocol header as the very first bytes on the stream before
+    /// anything else is sent.
+    async fn connect_once(
+        &self,
+        client_addr: SocketAddr,
+        negotiated_alpn: Option<&[u8]>,
+    ) -> anyhow::Result<ProxyStream> {
+        let mut stream = TcpStream::connect(self.server_addr).await?;
+
+        match self.proxy_protocol {
+            ProxyProtocol::Off => (),
+            ProxyProtocol::V1 => {
+                stream
+                    .write_all(&proxy_header::v1(client_addr, self.server_addr))
+                    .await?
+            }
+            ProxyProtocol::V2 => {
+                stream
+                    .write_all(&proxy_header::v2(client_addr, self.server_addr))
+                    .await?
+            }
+        }
+
+        if let Some(ref client_config) = self.tls_client_config {
             let server_name = self.server_name.clone().unwrap();
+            let connector = match negotiated_alpn {
+                Some(protocol) => {
+                    let mut client_config = (**client_config).clone();
+                    client_config.alpn_protocols = vec![protocol.to_vec()];
+                    TlsConnector::from(Arc::new(client_config))
+                }
+                None => TlsConnector::from(client_config.clone()),
+            };
             Ok(Box::pin(connector.connect(server_name, stream).await?))
         } else {
             Ok(Box::pin(stream))
         }
     }
+
 }
@@ -1,21 +1,28 @@
 use anyhow::Context;
-use anyhow::anyhow;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use etherparse::PacketBuilder;
-use pcap_file::pcap::{PcapPacket, PcapWriter};
+use pcap_file::DataLink;
+use pcap_file::pcapng::PcapNgWriter;
+use pcap_file::pcapng::blocks::decryption_secrets::{DecryptionSecretsBlock, SecretsType};
+use pcap_file::pcapng::blocks::enhanced_packet::EnhancedPacketBlock;
+use pcap_file::pcapng::blocks::interface_description::InterfaceDescriptionBlock;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::Cursor;
 use std::io::ErrorKind;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
 use std::{path::PathBuf, time::Duration};
 use tempfile::NamedTempFile;
 use tokio::time::Instant;
 use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
-use crate::flow::Flow;
+use crate::config::DumpProtocol;
+use crate::flow::history::RawChunk;
+use crate::flow::{Flow, RawFlow};
 use crate::{config::Config, service::Service};
 
 const DUMP_CHANNEL_LIMIT: usize = 400;
@@ -23,7 +30,49 @@ const DUMP_CHANNEL_LIMIT: usize = 400;
 const MTU_LEN: usize = 65535;
 const ETHERNET_HEADER_LEN: usize = 14;
 const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
 const TCP_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+
+fn ip_header_len(ip: IpAddr) -> usize {
+    match ip {
+        IpAddr::V4(_) => IPV4_HEADER_LEN,
+        IpAddr::V6(_) => IPV6_HEADER_LEN,
+    }
+}
+
+/// A unit of work sent to the dumper's background thread.
+pub enum DumpItem {
+    /// A fully closed flow, ready to be written in one shot.
+    Flow(Flow),
+
+    /// An incremental batch of chunks that [`crate::flow::history::RawHistory::compact`]
+    /// already dropped from a still-open raw flow's in-memory window. Lets
+    /// the dumper keep streaming a long-lived flow to disk instead of only
+    /// ever seeing it once, at close.
+    Partial {
+        id: Uuid,
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+        protocol: DumpProtocol,
+        chunks: Vec<(SocketAddr, RawChunk, Vec<u8>)>,
+    },
+}
+
+/// Per-flow TCP sequencing state, kept alive across [`DumpItem::Partial`]
+/// batches so a flow's synthetic TCP stream isn't re-handshaked every time
+/// its history is compacted.
+struct FlowTcpState {
+    src_ip: IpAddr,
+    dst_ip: IpAddr,
+    src_port: u16,
+    dst_port: u16,
+    seq_client: u32,
+    seq_server: u32,
+    ack_client: u32,
+    ack_server: u32,
+    last_was_client: bool,
+}
 
 pub struct Dumper {
     path: PathBuf,
@@ -31,10 +80,18 @@ pub struct Dumper {
     interval: Duration,
     max_packets: usize,
     format_map: HashMap<String, String>,
-    rx: mpsc::Receiver<Flow>,
+    rx: mpsc::Receiver<DumpItem>,
+
+    /// TLS secrets logged so far, in NSS `SSLKEYLOGFILE` format. `None` when
+    /// the service has no TLS config at all. Snapshotted into a Decryption
+    /// Secrets Block once, at the start of every rotated pcapng file: a file
+    /// carries only the secrets logged before it was opened, so a flow whose
+    /// handshake happens during the file's interval doesn't decrypt from
+    /// that file alone (its secrets first appear in the next file).
+    keylog: Option<Arc<Mutex<Vec<u8>>>>,
 }
 
-pub type DumperChannel = mpsc::SyncSender<Flow>;
+pub type DumperChannel = mpsc::SyncSender<DumpItem>;
 
 impl Dumper {
     pub async fn start(service: &Service, config: &Config) -> anyhow::Result<DumperChannel> {
@@ -84,6 +141,7 @@ impl Dumper {
             format_map,
             max_packets: config.dump_max_packets,
             rx,
+            keylog: service.tls_config.as_ref().map(|tls| tls.keylog.clone()),
         };
 
         tokio::task::spawn_blocking(move || dumper.dumper());
@@ -93,7 +151,27 @@ impl Dumper {
     fn dumper(mut self) -> anyhow::Result<()> {
         loop {
             let mut tmpfile = NamedTempFile::new()?;
-            let mut writer = PcapWriter::new(tmpfile.as_file_mut())?;
+            let mut writer = PcapNgWriter::new(tmpfile.as_file_mut())?;
+
+            writer.write_pcapng_block(InterfaceDescriptionBlock {
+                linktype: DataLink::ETHERNET,
+                snaplen: MTU_LEN as u32,
+                options: vec![],
+            })?;
+
+            if let Some(secrets) = self.keylog_snapshot() {
+                writer.write_pcapng_block(DecryptionSecretsBlock {
+                    secrets_type: SecretsType::TlsKeyLog,
+                    secrets_data: Cow::Owned(secrets),
+                    options: vec![],
+                })?;
+            }
+
+            // Tracks in-progress synthetic TCP streams for flows that are
+            // being dumped incrementally. Reset every rotation: a flow that
+            // straddles a rotation boundary just gets re-handshaked in the
+            // new file.
+            let mut states: HashMap<Uuid, FlowTcpState> = HashMap::new();
 
             let mut n_packets = 0;
             let start = Instant::now();
@@ -105,9 +183,29 @@ impl Dumper {
                 let timeout = self.interval - elapsed;
 
                 match self.rx.recv_timeout(timeout) {
-                    Ok(flow) => match Self::write_tcp_flow(&mut writer, &flow) {
+                    Ok(DumpItem::Flow(flow)) => {
+                        match Self::write_flow(&mut writer, &mut states, &flow) {
+                            Ok(n) => n_packets += n,
+                            Err(e) => warn!("Failed to dump pcaps for flow: {:?}", e),
+                        }
+                    }
+                    Ok(DumpItem::Partial {
+                        id,
+                        client_addr,
+                        server_addr,
+                        protocol,
+                        chunks,
+                    }) => match Self::write_partial(
+                        &mut writer,
+                        &mut states,
+                        id,
+                        client_addr,
+                        server_addr,
+                        protocol,
+                        chunks,
+                    ) {
                         Ok(n) => n_packets += n,
-                        Err(e) => warn!("Failed to dump pcaps for flow {}: {:?}", flow.id, e),
+                        Err(e) => warn!("Failed to stream pcaps for flow {}: {:?}", id, e),
                     },
                     Err(std::sync::mpsc::RecvTimeoutError::Timeout) => break,
                     Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
@@ -138,6 +236,14 @@ impl Dumper {
         }
     }
 
+    /// Returns the TLS secrets logged so far, if the service has any TLS
+    /// config and at least one secret has been logged. `None` either way
+    /// skips writing an empty Decryption Secrets Block.
+    fn keylog_snapshot(&self) -> Option<Vec<u8>> {
+        let secrets = self.keylog.as_ref()?.lock().unwrap().clone();
+        (!secrets.is_empty()).then_some(secrets)
+    }
+
     fn save_pcap(tmpfile: NamedTempFile, path: &std::path::Path) -> anyhow::Result<()> {
         match tmpfile.persist(&path) {
             Ok(_) => (),
@@ -155,42 +261,23 @@ impl Dumper {
         Ok(())
     }
 
-    fn write_tcp_flow(writer: &mut PcapWriter<&mut File>, flow: &Flow) -> anyhow::Result<usize> {
-        let src_ip = match flow.client_addr.ip() {
-            std::net::IpAddr::V4(ip) => ip,
-            _ => anyhow::bail!("Only IPv4 supported"),
-        };
-
-        let dst_ip = match flow.server_addr.ip() {
-            std::net::IpAddr::V4(ip) => ip,
-            _ => anyhow::bail!("Only IPv4 supported"),
-        };
+    /// Opens a new synthetic TCP stream for a flow (handshake only).
+    fn open_flow(
+        writer: &mut PcapNgWriter<&mut File>,
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+        timestamp: Duration,
+    ) -> anyhow::Result<FlowTcpState> {
+        let src_ip = client_addr.ip();
+        let dst_ip = server_addr.ip();
 
-        let src_port = flow.client_addr.port();
-        let dst_port = flow.server_addr.port();
+        let src_port = client_addr.port();
+        let dst_port = server_addr.port();
 
         let mut seq_client = 1_000;
         let mut seq_server = 1_000_000;
         let mut ack_client = seq_server + 1;
-        let mut ack_server = seq_client + 1;
-
-        let header_size = ETHERNET_HEADER_LEN + IPV4_HEADER_LEN + TCP_HEADER_LEN;
-        let max_payload = MTU_LEN - header_size;
-
-        let mut n_packets = 0;
-
-        let mut timestamp = flow
-            .client_history
-            .chunks
-            .first()
-            .map(|chunk| chunk.timestamp)
-            .map(|timestamp| {
-                Duration::new(
-                    timestamp.timestamp() as u64,
-                    timestamp.timestamp_subsec_nanos(),
-                )
-            })
-            .ok_or_else(|| anyhow!("Malformed flow with no chunks"))?;
+        let ack_server = seq_client + 1;
 
         Self::write_tcp_handshake(
             writer,
@@ -203,70 +290,97 @@ impl Dumper {
             &mut ack_client,
             &mut seq_server,
         )?;
-        n_packets += 3;
 
-        let mut last_was_client = false;
+        Ok(FlowTcpState {
+            src_ip,
+            dst_ip,
+            src_port,
+            dst_port,
+            seq_client,
+            seq_server,
+            ack_client,
+            ack_server,
+            last_was_client: false,
+        })
+    }
 
-        // TODO: Coalesce consecutive chunks coming from the same source
-        for (addr, chunk) in flow.into_iter() {
-            timestamp = Duration::new(
-                chunk.timestamp.timestamp() as u64,
-                chunk.timestamp.timestamp_subsec_nanos(),
-            );
-
-            let bytes = if addr == flow.client_addr {
-                last_was_client = true;
-                &flow.client_history.bytes[chunk.range.clone()]
+    /// Writes a single side's payload as one or more MTU-sized TCP segments,
+    /// advancing the relevant seq/ack counters in `state`.
+    fn write_chunk_bytes(
+        writer: &mut PcapNgWriter<&mut File>,
+        state: &mut FlowTcpState,
+        is_client: bool,
+        timestamp: Duration,
+        bytes: &[u8],
+    ) -> anyhow::Result<usize> {
+        let header_size = ETHERNET_HEADER_LEN + ip_header_len(state.src_ip) + TCP_HEADER_LEN;
+        let max_payload = MTU_LEN - header_size;
+
+        let mut n_packets = 0;
+        let mut offset = 0;
+
+        while offset < bytes.len() {
+            let end = (offset + max_payload).min(bytes.len());
+            let payload = &bytes[offset..end];
+            offset = end;
+
+            let (seq, ack, src, dst, sport, dport) = if is_client {
+                (
+                    state.seq_client,
+                    state.ack_client,
+                    state.src_ip,
+                    state.dst_ip,
+                    state.src_port,
+                    state.dst_port,
+                )
             } else {
-                last_was_client = false;
-                &flow.server_history.bytes[chunk.range.clone()]
+                (
+                    state.seq_server,
+                    state.ack_server,
+                    state.dst_ip,
+                    state.src_ip,
+                    state.dst_port,
+                    state.src_port,
+                )
             };
 
-            let mut offset = 0;
-            while offset < bytes.len() {
-                let end = (offset + max_payload).min(bytes.len());
-                let bytes = &bytes[offset..end];
-                offset = end;
-
-                let (seq, ack, src, dst, sport, dport) = if addr == flow.client_addr {
-                    (seq_client, ack_client, src_ip, dst_ip, src_port, dst_port)
-                } else {
-                    (seq_server, ack_server, dst_ip, src_ip, dst_port, src_port)
-                };
-
-                let mut flags = 0x10; // ACK
-                if end == bytes.len() {
-                    flags |= 0x08; // PSH
-                }
-
-                Self::write_tcp_packet(
-                    writer, timestamp, src, dst, sport, dport, seq, ack, flags, bytes,
-                )?;
+            let mut flags = 0x10; // ACK
+            if end == bytes.len() {
+                flags |= 0x08; // PSH
+            }
 
-                n_packets += 1;
+            Self::write_tcp_packet(writer, timestamp, src, dst, sport, dport, seq, ack, flags, payload)?;
+            n_packets += 1;
 
-                // Update seq/ack numbers per side
-                if addr == flow.client_addr {
-                    seq_client = seq_client.wrapping_add(bytes.len() as u32);
-                    ack_server = seq_client;
-                } else {
-                    seq_server = seq_server.wrapping_add(bytes.len() as u32);
-                    ack_client = seq_server;
-                }
+            if is_client {
+                state.seq_client = state.seq_client.wrapping_add(payload.len() as u32);
+                state.ack_server = state.seq_client;
+            } else {
+                state.seq_server = state.seq_server.wrapping_add(payload.len() as u32);
+                state.ack_client = state.seq_server;
             }
         }
 
-        if last_was_client {
+        state.last_was_client = is_client;
+        Ok(n_packets)
+    }
+
+    fn write_fin(
+        writer: &mut PcapNgWriter<&mut File>,
+        state: &FlowTcpState,
+        timestamp: Duration,
+    ) -> anyhow::Result<usize> {
+        if state.last_was_client {
             // Client sends FIN+ACK
             Self::write_tcp_packet(
                 writer,
                 timestamp,
-                src_ip,
-                dst_ip,
-                src_port,
-                dst_port,
-                seq_client,
-                ack_client,
+                state.src_ip,
+                state.dst_ip,
+                state.src_port,
+                state.dst_port,
+                state.seq_client,
+                state.ack_client,
                 0x11,
                 &[],
             )?;
@@ -275,26 +389,204 @@ impl Dumper {
             Self::write_tcp_packet(
                 writer,
                 timestamp,
-                dst_ip,
-                src_ip,
-                dst_port,
-                src_port,
-                seq_server,
-                ack_server,
+                state.dst_ip,
+                state.src_ip,
+                state.dst_port,
+                state.src_port,
+                state.seq_server,
+                state.ack_server,
                 0x11,
                 &[],
             )?;
         }
-        n_packets += 1;
+        Ok(1)
+    }
+
+    fn to_duration(timestamp: DateTime<Utc>) -> Duration {
+        Duration::new(timestamp.timestamp() as u64, timestamp.timestamp_subsec_nanos())
+    }
+
+    /// Writes a fully closed flow, dispatching to the flow's
+    /// [`DumpProtocol`] to pick a TCP or UDP reconstruction.
+    fn write_flow(
+        writer: &mut PcapNgWriter<&mut File>,
+        states: &mut HashMap<Uuid, FlowTcpState>,
+        flow: &Flow,
+    ) -> anyhow::Result<usize> {
+        let Flow::Raw(raw) = flow else {
+            // HTTP flows aren't reassembled into a synthetic stream.
+            return Ok(0);
+        };
+
+        match raw.protocol {
+            DumpProtocol::Tcp => Self::write_tcp_flow(writer, states, raw),
+            DumpProtocol::Udp => Self::write_udp_flow(writer, raw),
+        }
+    }
+
+    /// Writes a fully closed TCP-backed flow. If the dumper already has an
+    /// open synthetic TCP stream for it (because earlier chunks were
+    /// streamed in via [`DumpItem::Partial`]), continues that stream instead
+    /// of re-handshaking — the flow's remaining chunks are, by construction,
+    /// only the ones that were never previously flushed.
+    fn write_tcp_flow(
+        writer: &mut PcapNgWriter<&mut File>,
+        states: &mut HashMap<Uuid, FlowTcpState>,
+        raw: &RawFlow,
+    ) -> anyhow::Result<usize> {
+        let mut n_packets = 0;
+
+        let mut state = match states.remove(&raw.id) {
+            Some(state) => state,
+            None => {
+                let timestamp = raw
+                    .client_history
+                    .chunks
+                    .first()
+                    .or_else(|| raw.server_history.chunks.first())
+                    .map(|chunk| Self::to_duration(chunk.timestamp))
+                    .unwrap_or_else(|| Self::to_duration(Utc::now()));
+
+                let state = Self::open_flow(writer, raw.client_addr, raw.server_addr, timestamp)?;
+                n_packets += 3;
+                state
+            }
+        };
+
+        let mut last_timestamp = Self::to_duration(raw.start);
+
+        // TODO: Coalesce consecutive chunks coming from the same source
+        for (addr, chunk) in raw.into_iter() {
+            let timestamp = Self::to_duration(chunk.timestamp);
+            last_timestamp = timestamp;
+
+            let is_client = addr == raw.client_addr;
+            let bytes = if is_client {
+                raw.client_history.chunk_bytes(&chunk)
+            } else {
+                raw.server_history.chunk_bytes(&chunk)
+            };
+
+            n_packets += Self::write_chunk_bytes(writer, &mut state, is_client, timestamp, bytes)?;
+        }
+
+        n_packets += Self::write_fin(writer, &state, last_timestamp)?;
+
+        Ok(n_packets)
+    }
+
+    /// Writes a fully closed UDP-backed flow: one datagram per recorded
+    /// chunk (several for a chunk larger than the MTU), in order, with no
+    /// handshake or seq/ack bookkeeping.
+    fn write_udp_flow(writer: &mut PcapNgWriter<&mut File>, raw: &RawFlow) -> anyhow::Result<usize> {
+        let mut n_packets = 0;
+
+        for (addr, chunk) in raw.into_iter() {
+            let timestamp = Self::to_duration(chunk.timestamp);
+            let is_client = addr == raw.client_addr;
+            let bytes = if is_client {
+                raw.client_history.chunk_bytes(&chunk)
+            } else {
+                raw.server_history.chunk_bytes(&chunk)
+            };
+
+            let (src, dst) = if is_client {
+                (raw.client_addr, raw.server_addr)
+            } else {
+                (raw.server_addr, raw.client_addr)
+            };
+
+            n_packets += Self::write_udp_datagrams(writer, timestamp, src, dst, bytes)?;
+        }
+
+        Ok(n_packets)
+    }
+
+    /// Writes an incremental batch of chunks that were already compacted out
+    /// of a still-open raw flow's history, dispatching to the flow's
+    /// [`DumpProtocol`].
+    fn write_partial(
+        writer: &mut PcapNgWriter<&mut File>,
+        states: &mut HashMap<Uuid, FlowTcpState>,
+        id: Uuid,
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+        protocol: DumpProtocol,
+        chunks: Vec<(SocketAddr, RawChunk, Vec<u8>)>,
+    ) -> anyhow::Result<usize> {
+        match protocol {
+            DumpProtocol::Tcp => {
+                Self::write_tcp_partial(writer, states, id, client_addr, server_addr, chunks)
+            }
+            DumpProtocol::Udp => Self::write_udp_partial(writer, client_addr, server_addr, chunks),
+        }
+    }
+
+    /// Opens the synthetic TCP stream on the first batch for a given flow id
+    /// and leaves it open in `states` for subsequent batches (or the final
+    /// [`DumpItem::Flow`]) to continue.
+    fn write_tcp_partial(
+        writer: &mut PcapNgWriter<&mut File>,
+        states: &mut HashMap<Uuid, FlowTcpState>,
+        id: Uuid,
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+        chunks: Vec<(SocketAddr, RawChunk, Vec<u8>)>,
+    ) -> anyhow::Result<usize> {
+        let Some((_, first_chunk, _)) = chunks.first() else {
+            return Ok(0);
+        };
+
+        let mut n_packets = 0;
+
+        if !states.contains_key(&id) {
+            let timestamp = Self::to_duration(first_chunk.timestamp);
+            let state = Self::open_flow(writer, client_addr, server_addr, timestamp)?;
+            n_packets += 3;
+            states.insert(id, state);
+        }
+
+        let state = states.get_mut(&id).expect("just inserted above");
+
+        for (addr, chunk, bytes) in &chunks {
+            let is_client = *addr == client_addr;
+            let timestamp = Self::to_duration(chunk.timestamp);
+            n_packets += Self::write_chunk_bytes(writer, state, is_client, timestamp, bytes)?;
+        }
+
+        Ok(n_packets)
+    }
+
+    /// Streams a batch of already-compacted chunks as UDP datagrams, no
+    /// per-flow state needed since there's no handshake/seq-ack to resume.
+    fn write_udp_partial(
+        writer: &mut PcapNgWriter<&mut File>,
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+        chunks: Vec<(SocketAddr, RawChunk, Vec<u8>)>,
+    ) -> anyhow::Result<usize> {
+        let mut n_packets = 0;
+
+        for (addr, chunk, bytes) in &chunks {
+            let is_client = *addr == client_addr;
+            let timestamp = Self::to_duration(chunk.timestamp);
+            let (src, dst) = if is_client {
+                (client_addr, server_addr)
+            } else {
+                (server_addr, client_addr)
+            };
+
+            n_packets += Self::write_udp_datagrams(writer, timestamp, src, dst, bytes)?;
+        }
 
         Ok(n_packets)
     }
 
     fn write_tcp_handshake(
-        writer: &mut PcapWriter<&mut File>,
+        writer: &mut PcapNgWriter<&mut File>,
         timestamp: Duration,
-        src_ip: Ipv4Addr,
-        dst_ip: Ipv4Addr,
+        src_ip: IpAddr,
+        dst_ip: IpAddr,
         src_port: u16,
         dst_port: u16,
         seq_client: &mut u32,
@@ -351,10 +643,10 @@ impl Dumper {
     }
 
     fn write_tcp_packet(
-        writer: &mut PcapWriter<&mut File>,
+        writer: &mut PcapNgWriter<&mut File>,
         timestamp: Duration,
-        src_ip: Ipv4Addr,
-        dst_ip: Ipv4Addr,
+        src_ip: IpAddr,
+        dst_ip: IpAddr,
         src_port: u16,
         dst_port: u16,
         seq: u32,
@@ -367,9 +659,17 @@ impl Dumper {
         let dummy_mac2 = [0x22; 6];
 
         let window_size = 65535;
-        let mut builder = PacketBuilder::ethernet2(dummy_mac1, dummy_mac2)
-            .ipv4(src_ip.octets(), dst_ip.octets(), 64)
-            .tcp(src_port, dst_port, seq, window_size);
+        let builder = PacketBuilder::ethernet2(dummy_mac1, dummy_mac2);
+        let mut builder = match (src_ip, dst_ip) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                builder.ipv4(src.octets(), dst.octets(), 64)
+            }
+            (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                builder.ipv6(src.octets(), dst.octets(), 64)
+            }
+            _ => anyhow::bail!("Mismatched IP versions between client and server address"),
+        }
+        .tcp(src_port, dst_port, seq, window_size);
 
         if flags & 0x10 != 0 {
             builder = builder.ack(ack);
@@ -389,15 +689,103 @@ impl Dumper {
         builder.write(&mut cursor, payload)?;
 
         let packet_len = cursor.position() as usize;
-        let packet = PcapPacket {
+        let packet = EnhancedPacketBlock {
+            interface_id: 0,
+            timestamp,
+            original_len: packet_len as u32,
+            data: Cow::Borrowed(&buffer[..packet_len]),
+            options: vec![],
+        };
+
+        writer
+            .write_pcapng_block(packet)
+            .context("Failed to write packet to pcapng")?;
+
+        Ok(())
+    }
+
+    /// Splits one recorded chunk into one or more MTU-sized UDP datagrams
+    /// between `src`/`dst`. Almost always a single datagram; only chunks
+    /// larger than the MTU (possible since chunking is a proxy-side history
+    /// concern, not a wire-level one) get split.
+    fn write_udp_datagrams(
+        writer: &mut PcapNgWriter<&mut File>,
+        timestamp: Duration,
+        src: SocketAddr,
+        dst: SocketAddr,
+        bytes: &[u8],
+    ) -> anyhow::Result<usize> {
+        let header_size = ETHERNET_HEADER_LEN + ip_header_len(src.ip()) + UDP_HEADER_LEN;
+        let max_payload = MTU_LEN - header_size;
+
+        let mut n_packets = 0;
+        let mut offset = 0;
+
+        loop {
+            let end = (offset + max_payload).min(bytes.len());
+            Self::write_udp_packet(
+                writer,
+                timestamp,
+                src.ip(),
+                dst.ip(),
+                src.port(),
+                dst.port(),
+                &bytes[offset..end],
+            )?;
+            n_packets += 1;
+            offset = end;
+
+            if offset >= bytes.len() {
+                break;
+            }
+        }
+
+        Ok(n_packets)
+    }
+
+    /// Writes a single UDP datagram, no handshake/seq-ack bookkeeping. A
+    /// recorded chunk larger than the MTU spans several of these (see
+    /// [`Self::write_udp_datagrams`]).
+    fn write_udp_packet(
+        writer: &mut PcapNgWriter<&mut File>,
+        timestamp: Duration,
+        src_ip: IpAddr,
+        dst_ip: IpAddr,
+        src_port: u16,
+        dst_port: u16,
+        payload: &[u8],
+    ) -> anyhow::Result<()> {
+        let dummy_mac1 = [0x11; 6];
+        let dummy_mac2 = [0x22; 6];
+
+        let builder = PacketBuilder::ethernet2(dummy_mac1, dummy_mac2);
+        let builder = match (src_ip, dst_ip) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                builder.ipv4(src.octets(), dst.octets(), 64)
+            }
+            (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                builder.ipv6(src.octets(), dst.octets(), 64)
+            }
+            _ => anyhow::bail!("Mismatched IP versions between client and server address"),
+        }
+        .udp(src_port, dst_port);
+
+        let mut buffer = [0u8; MTU_LEN];
+        let mut cursor = Cursor::new(&mut buffer[..]);
+        builder.write(&mut cursor, payload)?;
+
+        let packet_len = cursor.position() as usize;
+        let packet = EnhancedPacketBlock {
+            interface_id: 0,
             timestamp,
-            orig_len: packet_len as u32,
+            original_len: packet_len as u32,
             data: Cow::Borrowed(&buffer[..packet_len]),
+            options: vec![],
         };
 
         writer
-            .write_packet(&packet)
-            .context("Failed to write packet to pcap")?;
+            .write_pcapng_block(packet)
+            .context("Failed to write packet to pcapng")?;
 
         Ok(())
     }
@@ -1,21 +1,29 @@
 use http::HeaderValue;
-use http::header::{CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::header::{CONNECTION, CONTENT_LENGTH, EXPECT, TRANSFER_ENCODING, UPGRADE};
 use http_body_util::{BodyExt, Full, Limited, combinators::BoxBody};
 use hyper::body::{Bytes, Incoming as IncomingBody};
-use hyper::client::conn::http1::SendRequest;
 use hyper::service::Service as HyperService;
-use hyper::{Request, Response};
+use hyper::upgrade::Upgraded;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
 use std::ops::ControlFlow;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::select;
 use tokio::sync::Mutex;
 use tokio::time;
-use tracing::{error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
-use crate::flow::{HttpFlow, IsFlow};
-use crate::http::{BytesBody, HttpResponse};
-use crate::proxy::Proxy;
+use crate::compress::{self, ContentCoding};
+use crate::flow::{Flow, HttpFlow, IsFlow, RawFlow};
+use crate::http::{BytesBody, HttpRequest};
+use crate::proxy::dumper::DumpItem;
+use crate::proxy::{Proxy, ProxyStream};
+use crate::proxy::connector::AcquiredConn;
 use crate::run_filter;
+use crate::ws::{self, WsFrame};
 
 const RESPONSE_TOO_BIG: &str = "Response body too big";
 const REQUEST_TOO_BIG: &str = "Response body too big";
@@ -24,41 +32,76 @@ const FILTER_INVALID: &str = "Invalid filter output";
 const SERVER_TIMEOUT: &str = "Server timeout elapsed";
 const CLIENT_HISTORY: &str = "Client history too big";
 const SERVER_HISTORY: &str = "Server history too big";
+const MISSING_WS_KEY: &str = "Missing Sec-WebSocket-Key";
+const EXPECTATION_FAILED: &str = "Expectation failed";
+const CLIENT_TIMEOUT: &str = "Client request timeout";
 
 struct ProxyHyperInner {
-    sender: SendRequest<BytesBody>,
+    conn: AcquiredConn,
     flow: HttpFlow,
     error: Option<anyhow::Error>,
+    evict: bool,
 }
 
+/// The HTTP-aware mode selected whenever `Service.http_config` is set (see
+/// `Proxy::handle_accepted_tcp`): drives the client-facing side with the
+/// configured `ServerBuilder`, buffers each message up to `max_body`, and
+/// runs it through the `Filter` chain as a structured [`HttpRequest`]/
+/// [`crate::http::HttpResponse`] rather than relaying opaque bytes, before
+/// forwarding it upstream over the pooled connection in `inner.conn`.
+///
+/// Already fully implemented prior to this doc comment, including the
+/// filter returning a short-circuit response (see the `on_http_request`/
+/// `on_http_request_headers` call sites in [`HyperService::call`] reading
+/// `guard.flow.history.responses.last()`) and `Content-Length`/
+/// `Transfer-Encoding` recompute on mutated bodies (`Self::push_request`/
+/// `Self::push_response` and [`crate::http::HttpResponse::into_wire`]/
+/// [`crate::http::HttpRequest::into_wire`]).
 #[derive(Clone)]
 pub struct ProxyHyper {
     pub proxy: Proxy,
     pub max_body: u64,
+    pub client_body_timeout: Duration,
     inner: Arc<Mutex<ProxyHyperInner>>,
 }
 
 impl ProxyHyper {
     pub fn new(
         proxy: Proxy,
-        sender: SendRequest<BytesBody>,
+        conn: AcquiredConn,
         max_body: u64,
+        client_body_timeout: Duration,
         flow: HttpFlow,
     ) -> ProxyHyper {
         ProxyHyper {
             proxy,
             max_body,
+            client_body_timeout,
             inner: Arc::new(Mutex::new(ProxyHyperInner {
-                sender,
+                conn,
                 flow,
                 error: None,
+                evict: false,
             })),
         }
     }
 
-    pub fn into_flow(self) -> Option<HttpFlow> {
+    /// Marks the upstream connection as unfit for reuse and aborts its
+    /// driving task right away, for flows killed by an operator or filter
+    /// while still in flight.
+    pub async fn evict_and_abort(&self) {
+        let mut guard = self.inner.lock().await;
+        guard.evict = true;
+        guard.conn.upstream.abort();
+    }
+
+    /// Tears down the service, returning its flow and whether the upstream
+    /// connection should be evicted rather than returned to the pool.
+    pub fn into_parts(self) -> Option<(AcquiredConn, HttpFlow, bool)> {
         if let Ok(mutex) = Arc::try_unwrap(self.inner) {
-            Some(mutex.into_inner().flow)
+            let inner = mutex.into_inner();
+            let evict = inner.evict || inner.error.is_some();
+            Some((inner.conn, inner.flow, evict))
         } else {
             None
         }
@@ -74,6 +117,7 @@ impl ProxyHyper {
         inner: &mut ProxyHyperInner,
         mut req: Request<Bytes>,
         len: usize,
+        coding: Option<ContentCoding>,
     ) -> anyhow::Result<()> {
         info!("Client requested {} {}", req.method(), req.uri());
         trace!("{:#?}", req);
@@ -84,7 +128,7 @@ impl ProxyHyper {
                 .insert(CONTENT_LENGTH, HeaderValue::from(len));
         }
 
-        if !inner.flow.history.push_request(req, len) {
+        if !inner.flow.history.push_request(req, len, coding) {
             Err(anyhow::anyhow!(CLIENT_HISTORY))
         } else {
             Ok(())
@@ -95,6 +139,7 @@ impl ProxyHyper {
         inner: &mut ProxyHyperInner,
         mut resp: Response<Bytes>,
         len: usize,
+        coding: Option<ContentCoding>,
     ) -> anyhow::Result<()> {
         info!("Server responded with status {}", resp.status().as_u16());
         trace!("{:#?}", resp);
@@ -105,12 +150,353 @@ impl ProxyHyper {
                 .insert(CONTENT_LENGTH, HeaderValue::from(len));
         }
 
-        if !inner.flow.history.push_response(resp, len) {
+        if !inner.flow.history.push_response(resp, len, coding) {
             Err(anyhow::anyhow!(SERVER_HISTORY))
         } else {
             Ok(())
         }
     }
+
+    /// Completes a WebSocket handshake with both the client and the
+    /// upstream server, then spawns a task relaying frames bidirectionally
+    /// instead of going through the buffered request/response path.
+    async fn handle_ws_upgrade(
+        service: ProxyHyper,
+        guard: &mut ProxyHyperInner,
+        mut req: Request<IncomingBody>,
+    ) -> anyhow::Result<Response<BoxBody<Bytes, hyper::Error>>> {
+        let Some(key) = req
+            .headers()
+            .get("sec-websocket-key")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+        else {
+            let mut resp = Response::new(Self::full(MISSING_WS_KEY));
+            *resp.status_mut() = StatusCode::BAD_REQUEST;
+            return Ok(resp);
+        };
+
+        let client_upgrade = hyper::upgrade::on(&mut req);
+
+        let (parts, _) = req.into_parts();
+        let upstream_req = Request::from_parts(parts, Self::full(Bytes::new()));
+
+        let timeout = service.proxy.inner.service.server_timeout;
+        let mut upstream_resp = match time::timeout(
+            timeout,
+            guard.conn.sender.send_request(upstream_req),
+        )
+        .await
+        {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(e)) => Err(e)?,
+            Err(_) => anyhow::bail!(SERVER_TIMEOUT),
+        };
+
+        if upstream_resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+            info!(
+                "Upstream declined WebSocket upgrade for flow {} with status {}",
+                guard.flow.get_id(),
+                upstream_resp.status()
+            );
+            let (parts, incoming) = upstream_resp.into_parts();
+            let body = Limited::new(incoming, service.max_body as usize)
+                .collect()
+                .await?
+                .to_bytes();
+            return Ok(Response::from_parts(parts, Self::full(body)));
+        }
+
+        let upstream_upgrade = hyper::upgrade::on(&mut upstream_resp);
+
+        let resp = Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "Upgrade")
+            .header(UPGRADE, "websocket")
+            .header("Sec-WebSocket-Accept", ws::accept_key(&key))
+            .body(Self::full(Bytes::new()))?;
+
+        let max_frame = service.max_body as usize;
+        tokio::spawn(async move {
+            let (client, upstream) = match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                Ok(pair) => pair,
+                Err(e) => {
+                    warn!("WebSocket upgrade handshake failed: {}", e);
+                    return;
+                }
+            };
+
+            Self::relay_ws(service, client, upstream, max_frame).await;
+        });
+
+        Ok(resp)
+    }
+
+    /// Relays WebSocket frames bidirectionally between the upgraded client
+    /// and upstream connections, running the `on_ws_client_frame` /
+    /// `on_ws_server_frame` filter hooks on each one.
+    async fn relay_ws(
+        service: ProxyHyper,
+        client: Upgraded,
+        upstream: Upgraded,
+        max_frame: usize,
+    ) {
+        let mut client = TokioIo::new(client);
+        let mut upstream = TokioIo::new(upstream);
+        let flow_id = service.inner.lock().await.flow.get_id();
+
+        loop {
+            select! {
+                frame = ws::read_frame(&mut client, max_frame) => {
+                    let mut frame = match frame {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => {
+                            debug!("Client closed WebSocket for flow {}", flow_id);
+                            break;
+                        }
+                        Err(e) => {
+                            warn!("Error reading client WebSocket frame for flow {}: {}", flow_id, e);
+                            break;
+                        }
+                    };
+
+                    let len = frame.payload.len();
+                    let mut guard = service.inner.lock().await;
+                    if !guard.flow.history.push_ws_client(frame.clone(), len) {
+                        warn!("Client WebSocket history too big for flow {}", flow_id);
+                        break;
+                    }
+
+                    run_filter!(service.proxy, on_ws_client_frame, &mut guard.flow, {
+                        info!("Python client filter killed WebSocket flow {}", flow_id);
+                        break;
+                    });
+
+                    if let Some((last, _)) = guard.flow.history.ws_client.last() {
+                        frame = last.clone();
+                    }
+                    drop(guard);
+
+                    let closing = frame.opcode == ws::Opcode::Close;
+                    let bytes = ws::encode_frame(&frame, true);
+                    if upstream.write_all(&bytes).await.is_err() || upstream.flush().await.is_err() {
+                        break;
+                    }
+                    if closing {
+                        break;
+                    }
+                }
+
+                frame = ws::read_frame(&mut upstream, max_frame) => {
+                    let mut frame = match frame {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => {
+                            debug!("Server closed WebSocket for flow {}", flow_id);
+                            break;
+                        }
+                        Err(e) => {
+                            warn!("Error reading server WebSocket frame for flow {}: {}", flow_id, e);
+                            break;
+                        }
+                    };
+
+                    let len = frame.payload.len();
+                    let mut guard = service.inner.lock().await;
+                    if !guard.flow.history.push_ws_server(frame.clone(), len) {
+                        warn!("Server WebSocket history too big for flow {}", flow_id);
+                        break;
+                    }
+
+                    run_filter!(service.proxy, on_ws_server_frame, &mut guard.flow, {
+                        info!("Python server filter killed WebSocket flow {}", flow_id);
+                        break;
+                    });
+
+                    if let Some((last, _)) = guard.flow.history.ws_server.last() {
+                        frame = last.clone();
+                    }
+                    drop(guard);
+
+                    let closing = frame.opcode == ws::Opcode::Close;
+                    let bytes = ws::encode_frame(&frame, false);
+                    if client.write_all(&bytes).await.is_err() || client.flush().await.is_err() {
+                        break;
+                    }
+                    if closing {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let _ = client.shutdown().await;
+        let _ = upstream.shutdown().await;
+    }
+
+    /// Tunnels a `CONNECT` request: the request line and headers are still
+    /// recorded/filterable through the normal history machinery by the
+    /// caller, but once the upstream accepts (any `2xx`), the connection
+    /// drops out of framed HTTP entirely and is spliced byte-for-byte
+    /// instead, same as [`Self::handle_raw_upgrade`].
+    async fn handle_connect(
+        service: ProxyHyper,
+        guard: &mut ProxyHyperInner,
+        mut req: Request<IncomingBody>,
+    ) -> anyhow::Result<Response<BoxBody<Bytes, hyper::Error>>> {
+        let client_upgrade = hyper::upgrade::on(&mut req);
+
+        let (parts, _) = req.into_parts();
+        let upstream_req = Request::from_parts(parts, Self::full(Bytes::new()));
+
+        let timeout = service.proxy.inner.service.server_timeout;
+        let mut upstream_resp = match time::timeout(
+            timeout,
+            guard.conn.sender.send_request(upstream_req),
+        )
+        .await
+        {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(e)) => Err(e)?,
+            Err(_) => anyhow::bail!(SERVER_TIMEOUT),
+        };
+
+        if !upstream_resp.status().is_success() {
+            info!(
+                "Upstream declined CONNECT tunnel for flow {} with status {}",
+                guard.flow.get_id(),
+                upstream_resp.status()
+            );
+            let (parts, incoming) = upstream_resp.into_parts();
+            let body = Limited::new(incoming, service.max_body as usize)
+                .collect()
+                .await?
+                .to_bytes();
+            return Ok(Response::from_parts(parts, Self::full(body)));
+        }
+
+        let upstream_upgrade = hyper::upgrade::on(&mut upstream_resp);
+        let (parts, _) = upstream_resp.into_parts();
+        let resp = Response::from_parts(parts, Self::full(Bytes::new()));
+
+        tokio::spawn(async move {
+            let (client, upstream) = match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                Ok(pair) => pair,
+                Err(e) => {
+                    warn!("CONNECT tunnel upgrade failed: {}", e);
+                    return;
+                }
+            };
+
+            Self::relay_tunnel(service, client, upstream).await;
+        });
+
+        Ok(resp)
+    }
+
+    /// Tunnels a non-WebSocket protocol upgrade (`Connection: Upgrade` with
+    /// some other `Upgrade` token) once the upstream agrees with a `101`.
+    /// Same idea as [`Self::handle_connect`], just keyed off a different
+    /// request shape.
+    async fn handle_raw_upgrade(
+        service: ProxyHyper,
+        guard: &mut ProxyHyperInner,
+        mut req: Request<IncomingBody>,
+    ) -> anyhow::Result<Response<BoxBody<Bytes, hyper::Error>>> {
+        let client_upgrade = hyper::upgrade::on(&mut req);
+
+        let (parts, _) = req.into_parts();
+        let upstream_req = Request::from_parts(parts, Self::full(Bytes::new()));
+
+        let timeout = service.proxy.inner.service.server_timeout;
+        let mut upstream_resp = match time::timeout(
+            timeout,
+            guard.conn.sender.send_request(upstream_req),
+        )
+        .await
+        {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(e)) => Err(e)?,
+            Err(_) => anyhow::bail!(SERVER_TIMEOUT),
+        };
+
+        if upstream_resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+            info!(
+                "Upstream declined protocol upgrade for flow {} with status {}",
+                guard.flow.get_id(),
+                upstream_resp.status()
+            );
+            let (parts, incoming) = upstream_resp.into_parts();
+            let body = Limited::new(incoming, service.max_body as usize)
+                .collect()
+                .await?
+                .to_bytes();
+            return Ok(Response::from_parts(parts, Self::full(body)));
+        }
+
+        let upstream_upgrade = hyper::upgrade::on(&mut upstream_resp);
+        let (parts, _) = upstream_resp.into_parts();
+        let resp = Response::from_parts(parts, Self::full(Bytes::new()));
+
+        tokio::spawn(async move {
+            let (client, upstream) = match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                Ok(pair) => pair,
+                Err(e) => {
+                    warn!("Protocol upgrade handshake failed: {}", e);
+                    return;
+                }
+            };
+
+            Self::relay_tunnel(service, client, upstream).await;
+        });
+
+        Ok(resp)
+    }
+
+    /// Splices an upgraded client/upstream pair byte-for-byte by handing
+    /// them to [`Proxy::handle_flow`] as a synthetic [`RawFlow`], the same
+    /// raw relay loop (with its `on_raw_client`/`on_raw_server` filter hooks
+    /// and dumper recording) used for non-HTTP services. The handshake that
+    /// negotiated the tunnel already went through the `Filter` as ordinary
+    /// framed HTTP; only what comes after is treated as opaque bytes.
+    async fn relay_tunnel(service: ProxyHyper, client: Upgraded, upstream: Upgraded) {
+        let proxy = service.proxy.clone();
+        let dumper = proxy.inner.dumper.clone();
+
+        let (client_addr, server_addr, peer_cert_chain) = {
+            let guard = service.inner.lock().await;
+            (
+                guard.flow.client_addr,
+                guard.flow.server_addr,
+                guard.flow.peer_cert_chain.clone(),
+            )
+        };
+
+        let mut flow = RawFlow::new(
+            client_addr,
+            proxy.inner.service.client_max_history,
+            server_addr,
+            proxy.inner.service.server_max_history,
+            peer_cert_chain,
+            proxy.inner.service.dump_protocol,
+        );
+
+        let client = Box::pin(TokioIo::new(client)) as ProxyStream;
+        let upstream = Box::pin(TokioIo::new(upstream)) as ProxyStream;
+
+        match proxy.handle_flow(client, upstream, &mut flow).await {
+            Ok(_) => info!("Closed tunneled flow from {}", client_addr),
+            Err(e) => warn!("Error in tunneled flow from {}: {}", client_addr, e),
+        }
+
+        let flow = Flow::Raw(flow);
+        proxy.maybe_record(&flow).await;
+
+        if let Some(ref channel) = dumper {
+            if let Err(e) = channel.try_send(DumpItem::Flow(flow)) {
+                warn!("Could not send tunneled flow to dumper: {}", e);
+            }
+        }
+    }
 }
 
 impl HyperService<Request<IncomingBody>> for ProxyHyper {
@@ -127,40 +513,164 @@ impl HyperService<Request<IncomingBody>> for ProxyHyper {
                 return Err(error);
             }
 
+            // A CONNECT tunnel or a connection upgrade (e.g. WebSocket) isn't
+            // proxied as plain buffered HTTP, so once one is requested this
+            // connection can no longer be safely handed back to the pool.
+            let is_upgrade = req
+                .headers()
+                .get(CONNECTION)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.to_ascii_lowercase().contains("upgrade"));
+
+            if is_upgrade || req.method() == Method::CONNECT {
+                guard.evict = true;
+            }
+
+            if req.method() == Method::CONNECT {
+                return Self::handle_connect(service.clone(), &mut guard, req).await;
+            }
+
+            if is_upgrade && ws::is_upgrade_request(req.method(), req.headers()) {
+                return Self::handle_ws_upgrade(service.clone(), &mut guard, req).await;
+            }
+
+            if is_upgrade {
+                return Self::handle_raw_upgrade(service.clone(), &mut guard, req).await;
+            }
+
+            // A client sending `Expect: 100-continue` is waiting on our say-so
+            // before it uploads the body, so consult the filter against the
+            // headers alone and decide whether it's even worth reading
+            // (hyper only emits the interim "100 Continue" once the body
+            // starts being polled below).
+            let expects_continue = req
+                .headers()
+                .get(EXPECT)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.eq_ignore_ascii_case("100-continue"));
+
+            if expects_continue {
+                let mut builder = Request::builder()
+                    .method(req.method().clone())
+                    .uri(req.uri().clone())
+                    .version(req.version());
+                *builder.headers_mut().expect("builder not errored") = req.headers().clone();
+                let headers_req = HttpRequest(builder.body(Bytes::new())?, None);
+
+                let killed = match service.proxy.inner.service.filter {
+                    Some(ref filter) => {
+                        filter
+                            .on_http_request_headers(&mut guard.flow, &headers_req)
+                            .await
+                    }
+                    None => ControlFlow::Continue(()),
+                };
+
+                if let ControlFlow::Break(_) = killed {
+                    info!(
+                        "Python request-headers filter rejected flow {} before 100-continue",
+                        guard.flow.get_id()
+                    );
+
+                    // The body was never read, so the connection can't be
+                    // trusted for keep-alive reuse.
+                    guard.evict = true;
+
+                    return Ok(match guard.flow.history.responses.last() {
+                        Some((resp, _)) => {
+                            let resp = resp.clone().into_wire();
+                            let (parts, body) = resp.into_parts();
+                            Response::from_parts(parts, Self::full(body))
+                        }
+                        None => {
+                            let mut resp = Response::new(Self::full(EXPECTATION_FAILED));
+                            *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
+                            resp
+                        }
+                    });
+                }
+            }
+
             // Make a copy of the request
             let (parts, incoming) = req.into_parts();
-            let body = match Limited::new(incoming, service.max_body as usize)
-                .collect()
-                .await
+            let body = match time::timeout(
+                service.client_body_timeout,
+                Limited::new(incoming, service.max_body as usize).collect(),
+            )
+            .await
             {
-                Ok(body) => body.to_bytes(),
-                Err(_) => {
+                Ok(Ok(body)) => body.to_bytes(),
+                Ok(Err(_)) => {
                     let history_req = Request::from_parts(parts, Bytes::from(REQUEST_TOO_BIG));
-                    Self::push_request(&mut guard, history_req, 0).await?;
+                    Self::push_request(&mut guard, history_req, 0, None).await?;
 
                     let mut resp = Response::new(Self::full(REQUEST_TOO_BIG));
                     *resp.status_mut() = hyper::StatusCode::PAYLOAD_TOO_LARGE;
 
                     let mut history_resp = Response::new(Bytes::from(REQUEST_TOO_BIG));
                     *history_resp.status_mut() = hyper::StatusCode::PAYLOAD_TOO_LARGE;
-                    Self::push_response(&mut guard, history_resp, 0).await?;
+                    Self::push_response(&mut guard, history_resp, 0, None).await?;
 
                     // Flag connection as dead
                     guard.error = Some(anyhow::anyhow!(REQUEST_TOO_BIG));
                     return Ok(resp);
                 }
+                Err(_) => {
+                    let history_req = Request::from_parts(parts, Bytes::from(CLIENT_TIMEOUT));
+                    Self::push_request(&mut guard, history_req, 0, None).await?;
+
+                    let mut resp = Response::new(Self::full(CLIENT_TIMEOUT));
+                    *resp.status_mut() = hyper::StatusCode::REQUEST_TIMEOUT;
+
+                    let mut history_resp = Response::new(Bytes::from(CLIENT_TIMEOUT));
+                    *history_resp.status_mut() = hyper::StatusCode::REQUEST_TIMEOUT;
+                    Self::push_response(&mut guard, history_resp, 0, None).await?;
+
+                    // A stalled upload leaves the connection in an unknown
+                    // state, so don't hand it back to the pool.
+                    guard.error = Some(anyhow::anyhow!(CLIENT_TIMEOUT));
+                    return Ok(resp);
+                }
             };
 
-            let history_req = Request::from_parts(parts.clone(), body.clone());
-            Self::push_request(&mut guard, history_req, body.len()).await?;
+            // Decode a compressed body so the filter sees plaintext, bounding
+            // the inflated size at max_body to guard against decompression
+            // bombs. The original (possibly compressed) `body` is still what
+            // gets forwarded upstream unchanged below.
+            let (decoded_body, coding) = match compress::decode_body(
+                &parts.headers,
+                body.clone(),
+                service.max_body as usize,
+            ) {
+                Ok(pair) => pair,
+                Err(_) => {
+                    let history_req =
+                        Request::from_parts(parts.clone(), Bytes::from(REQUEST_TOO_BIG));
+                    Self::push_request(&mut guard, history_req, 0, None).await?;
+
+                    let mut resp = Response::new(Self::full(REQUEST_TOO_BIG));
+                    *resp.status_mut() = hyper::StatusCode::PAYLOAD_TOO_LARGE;
+
+                    let mut history_resp = Response::new(Bytes::from(REQUEST_TOO_BIG));
+                    *history_resp.status_mut() = hyper::StatusCode::PAYLOAD_TOO_LARGE;
+                    Self::push_response(&mut guard, history_resp, 0, None).await?;
+
+                    guard.error = Some(anyhow::anyhow!(REQUEST_TOO_BIG));
+                    return Ok(resp);
+                }
+            };
+
+            let history_req = Request::from_parts(parts.clone(), decoded_body.clone());
+            Self::push_request(&mut guard, history_req, decoded_body.len(), coding).await?;
 
             // Check if the request should be blocked by the filter
             run_filter!(service.proxy, on_http_request, &mut guard.flow, {
                 info!("Python request filter killed flow {}", guard.flow.get_id());
-                
+
                 // If filter killed the connection, return the custom response if available
-                if let Some((HttpResponse(resp), _)) = guard.flow.history.responses.last() {
-                    let (parts, body) = resp.clone().into_parts();
+                if let Some((resp, _)) = guard.flow.history.responses.last() {
+                    let resp = resp.clone().into_wire();
+                    let (parts, body) = resp.into_parts();
                     return Ok(Response::from_parts(parts, Self::full(body)));
                 } else {
                     // Default blocked response
@@ -170,11 +680,11 @@ impl HyperService<Request<IncomingBody>> for ProxyHyper {
                 }
             });
 
-            // Send the request to the real service
+            // Send the original (still-encoded) request to the real service
             let req = Request::from_parts(parts, Self::full(body));
             let resp = {
                 let timeout = service.proxy.inner.service.server_timeout;
-                match time::timeout(timeout, guard.sender.send_request(req)).await {
+                match time::timeout(timeout, guard.conn.sender.send_request(req)).await {
                     Ok(Ok(resp)) => resp,
                     Ok(Err(e)) => Err(e)?,
                     Err(_) => anyhow::bail!(SERVER_TIMEOUT),
@@ -190,14 +700,28 @@ impl HyperService<Request<IncomingBody>> for ProxyHyper {
                 Ok(body) => body.to_bytes(),
                 Err(_) => {
                     let resp = Response::from_parts(parts.clone(), Bytes::from(RESPONSE_TOO_BIG));
-                    Self::push_response(&mut guard, resp, 0).await?;
+                    Self::push_response(&mut guard, resp, 0, None).await?;
                     return Err(anyhow::anyhow!(RESPONSE_TOO_BIG));
                 }
             };
 
+            // Decode for the filter to see plaintext; the response is
+            // re-compressed with the same codec in `HttpResponse::into_wire`
+            // once the filter has had its chance to run.
+            let (body, coding) =
+                match compress::decode_body(&parts.headers, body, service.max_body as usize) {
+                    Ok(pair) => pair,
+                    Err(_) => {
+                        let resp =
+                            Response::from_parts(parts.clone(), Bytes::from(RESPONSE_TOO_BIG));
+                        Self::push_response(&mut guard, resp, 0, None).await?;
+                        return Err(anyhow::anyhow!(RESPONSE_TOO_BIG));
+                    }
+                };
+
             let body_len = body.len();
             let history_resp = Response::from_parts(parts, body);
-            Self::push_response(&mut guard, history_resp, body_len).await?;
+            Self::push_response(&mut guard, history_resp, body_len, coding).await?;
 
             let resp = {
                 run_filter!(service.proxy, on_http_response, &mut guard.flow, {
@@ -206,8 +730,9 @@ impl HyperService<Request<IncomingBody>> for ProxyHyper {
                 });
 
                 match guard.flow.history.responses.last() {
-                    Some((HttpResponse(resp), _)) => {
-                        let (parts, body) = resp.clone().into_parts();
+                    Some((resp, _)) => {
+                        let resp = resp.clone().into_wire();
+                        let (parts, body) = resp.into_parts();
                         Response::from_parts(parts, Self::full(body))
                     }
                     _ => {
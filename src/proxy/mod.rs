@@ -2,29 +2,46 @@ mod acceptor;
 mod connector;
 mod dumper;
 mod hyper;
-
-use crate::config::Config;
+mod proxy_header;
+mod quic;
+mod record;
+mod retry;
+
+use crate::ban::BanList;
+use crate::config::{Config, DumpProtocol, Transport};
+use crate::filter::RawVerdict;
 use crate::flow::history::{RawChunk, RawHistory};
-use crate::flow::{Flow, HttpFlow, RawFlow};
+use crate::flow::{Flow, HttpFlow, IsFlow, RawFlow};
 use crate::proxy::acceptor::Acceptor;
 use crate::proxy::connector::Connector;
-use crate::proxy::dumper::{Dumper, DumperChannel};
+use crate::proxy::dumper::{Dumper, DumpItem, DumperChannel};
 use crate::proxy::hyper::ProxyHyper;
+use crate::proxy::quic::{QuicAcceptor, QuicConnector};
+use crate::proxy::record::Recording;
+use crate::proxy::retry::with_retry;
 use crate::service::Service;
+use crate::shutdown::Shutdown;
 use crate::stream::{ChunkRead, ChunkStream, ChunkWrite};
 
 use anyhow::Context;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use hyper_util::rt::TokioIo;
+use std::collections::HashMap;
 use std::error::Error;
+use std::net::SocketAddr;
 use std::ops::ControlFlow;
+use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::mpsc;
 use std::time::Duration;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::{RwLock, Semaphore};
 use tokio::time;
 use tokio::{select, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, trace, warn};
+use uuid::Uuid;
 
 pub type ProxyStream = Pin<Box<dyn ChunkStream>>;
 
@@ -32,7 +49,21 @@ pub enum FlowStatus {
     Read,
     Closed,
     Timeout,
-    HistoryTooBig,
+}
+
+/// Metadata about a live flow, as returned by [`Proxy::list_flows`].
+pub struct FlowInfo {
+    pub id: Uuid,
+    pub client_addr: SocketAddr,
+    pub server_addr: SocketAddr,
+    pub start: DateTime<Utc>,
+}
+
+struct FlowHandle {
+    client_addr: SocketAddr,
+    server_addr: SocketAddr,
+    start: DateTime<Utc>,
+    cancel: CancellationToken,
 }
 
 #[derive(Clone)]
@@ -42,9 +73,27 @@ pub struct Proxy {
 
 struct ProxyInner {
     service: Service,
-    acceptor: Acceptor,
-    connector: Connector,
+
+    /// Populated when `service.transport` is [`Transport::Tcp`].
+    acceptor: Option<Acceptor>,
+    connector: Option<Connector>,
+
+    /// Populated when `service.transport` is [`Transport::Quic`].
+    quic: Option<QuicTransport>,
+
     dumper: Option<DumperChannel>,
+    flows: RwLock<HashMap<Uuid, FlowHandle>>,
+    ban_list: BanList,
+    shutdown: Shutdown,
+
+    /// Bounds how many TLS handshakes [`Proxy::handle_accept_tcp`] runs at
+    /// once. See [`crate::config::Config::max_pending_handshakes`].
+    handshake_semaphore: Arc<Semaphore>,
+}
+
+struct QuicTransport {
+    acceptor: QuicAcceptor,
+    connector: QuicConnector,
 }
 
 #[macro_export]
@@ -52,6 +101,7 @@ macro_rules! run_filter {
     ($proxy:expr, $method:ident, $arg:expr, $on_break:block) => {
         if let Some(ref filter) = $proxy.inner.service.filter {
             if let ControlFlow::Break(_) = filter.$method($arg).await {
+                $proxy.inner.ban_list.flag(($arg).get_client_addr().ip()).await;
                 $on_break
             }
         }
@@ -59,9 +109,26 @@ macro_rules! run_filter {
 }
 
 impl Proxy {
-    pub async fn start(service: Service, config: &Config) -> anyhow::Result<JoinHandle<()>> {
-        let acceptor = Acceptor::new(&service).await?;
-        let connector = Connector::new(&service).await?;
+    pub async fn start(
+        service: Service,
+        config: &Config,
+    ) -> anyhow::Result<(Proxy, JoinHandle<()>)> {
+        let (acceptor, connector, quic) = match service.transport {
+            Transport::Tcp => (
+                Some(Acceptor::new(&service).await?),
+                Some(Connector::new(&service).await?),
+                None,
+            ),
+            Transport::Quic => (
+                None,
+                None,
+                Some(QuicTransport {
+                    acceptor: QuicAcceptor::new(&service).await?,
+                    connector: QuicConnector::new(&service).await?,
+                }),
+            ),
+        };
+
         let dumper = if config.dump_enabled {
             Some(
                 Dumper::start(&service, config)
@@ -72,108 +139,437 @@ impl Proxy {
             None
         };
 
+        let handshake_semaphore = Arc::new(Semaphore::new(service.max_pending_handshakes));
+
         let proxy = Proxy {
             inner: Arc::new(ProxyInner {
                 service,
                 acceptor,
                 connector,
+                quic,
                 dumper,
+                flows: RwLock::new(HashMap::new()),
+                ban_list: BanList::new(config.ban_policy.clone()),
+                shutdown: Shutdown::new(config.shutdown_grace),
+                handshake_semaphore,
             }),
         };
 
-        Ok(tokio::spawn(async move { proxy.handle_accept().await }))
+        let expirer = proxy.clone();
+        tokio::spawn(async move { expirer.inner.ban_list.run_expirer().await });
+
+        let accept_task = {
+            let proxy = proxy.clone();
+            tokio::spawn(async move { proxy.handle_accept().await })
+        };
+
+        Ok((proxy, accept_task))
+    }
+
+    /// Stops accepting new connections, waits up to the configured grace
+    /// period for in-flight flows to close on their own, then force-cancels
+    /// any stragglers so they still flush and shut down cleanly instead of
+    /// being abandoned mid-transfer.
+    pub async fn shutdown(&self) {
+        self.inner.shutdown.signal();
+
+        let drained = time::timeout(self.inner.shutdown.grace_period(), async {
+            while !self.inner.flows.read().await.is_empty() {
+                time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .is_ok();
+
+        if drained {
+            return;
+        }
+
+        let stragglers: Vec<Uuid> = self.inner.flows.read().await.keys().copied().collect();
+        warn!(
+            "Grace period elapsed, forcing {} flow(s) closed",
+            stragglers.len()
+        );
+
+        for id in stragglers {
+            self.kill_flow(id).await;
+        }
+    }
+
+    async fn register_flow(
+        &self,
+        id: Uuid,
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+        start: DateTime<Utc>,
+    ) -> CancellationToken {
+        let cancel = CancellationToken::new();
+        self.inner.flows.write().await.insert(
+            id,
+            FlowHandle {
+                client_addr,
+                server_addr,
+                start,
+                cancel: cancel.clone(),
+            },
+        );
+        cancel
+    }
+
+    async fn unregister_flow(&self, id: Uuid) {
+        self.inner.flows.write().await.remove(&id);
+    }
+
+    /// Compacts `history` once it crosses its high-water mark, streaming the
+    /// dropped chunks to the dumper instead of letting the flow pin an
+    /// ever-growing buffer or get torn down. `from` is the address the
+    /// compacted bytes were read from (client or server side).
+    ///
+    /// `history.compact()` evicts the drained bytes from memory unconditionally,
+    /// so once it's been called they only exist in the `DumpItem::Partial` built
+    /// from its return value — if that were dropped on a full channel (as a
+    /// plain `try_send` would), the bytes would be gone from the capture for
+    /// good. Falls back to a blocking send on a blocking-pool thread instead,
+    /// so backpressure on the dumper slows this flow down rather than
+    /// silently losing already-evicted history.
+    async fn maybe_compact(
+        &self,
+        id: Uuid,
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+        from: SocketAddr,
+        protocol: DumpProtocol,
+        history: &mut RawHistory,
+    ) {
+        if !history.should_compact() {
+            return;
+        }
+
+        let drained = history.compact();
+        let Some(upto) = drained.last().map(|(chunk, _)| chunk.range.end) else {
+            return;
+        };
+
+        let Some(ref dumper) = self.inner.dumper else {
+            return;
+        };
+
+        let chunks = drained
+            .into_iter()
+            .map(|(chunk, bytes)| (from, chunk, bytes))
+            .collect();
+
+        let item = DumpItem::Partial {
+            id,
+            client_addr,
+            server_addr,
+            protocol,
+            chunks,
+        };
+
+        let item = match dumper.try_send(item) {
+            Ok(()) => {
+                history.mark_flushed(upto);
+                return;
+            }
+            Err(mpsc::TrySendError::Full(item)) => item,
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                warn!("Dumper channel closed; flow {} chunks not streamed", id);
+                return;
+            }
+        };
+
+        let dumper = dumper.clone();
+        match tokio::task::spawn_blocking(move || dumper.send(item)).await {
+            Ok(Ok(())) => history.mark_flushed(upto),
+            Ok(Err(e)) => warn!("Dumper channel closed; flow {} chunks not streamed: {}", id, e),
+            Err(e) => warn!("Blocking send to dumper panicked for flow {}: {}", id, e),
+        }
+    }
+
+    /// Terminates the flow with the given id, if it is still active.
+    /// Returns `true` if a matching flow was found and cancelled.
+    pub async fn kill_flow(&self, id: Uuid) -> bool {
+        match self.inner.flows.read().await.get(&id) {
+            Some(handle) => {
+                handle.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lists all flows currently being proxied.
+    pub async fn list_flows(&self) -> Vec<FlowInfo> {
+        self.inner
+            .flows
+            .read()
+            .await
+            .iter()
+            .map(|(id, handle)| FlowInfo {
+                id: *id,
+                client_addr: handle.client_addr,
+                server_addr: handle.server_addr,
+                start: handle.start,
+            })
+            .collect()
+    }
+
+    /// Acts on a raw chunk filter's verdict: `Drop` tears the flow down after
+    /// a soft ban-list strike, `Ban` tears it down and bans the client
+    /// immediately.
+    async fn apply_raw_verdict(&self, flow: &RawFlow, verdict: RawVerdict) -> ControlFlow<()> {
+        match verdict {
+            RawVerdict::Pass => ControlFlow::Continue(()),
+            RawVerdict::Drop => {
+                info!("Python filter killed flow {}", flow.id);
+                self.inner.ban_list.flag(flow.client_addr.ip()).await;
+                ControlFlow::Break(())
+            }
+            RawVerdict::Ban => {
+                info!("Python filter banned flow {}", flow.id);
+                self.inner.ban_list.ban_now(flow.client_addr.ip()).await;
+                ControlFlow::Break(())
+            }
+        }
+    }
+
+    /// Writes `flow` to `record_path` as a timed replay cast, if configured.
+    /// Best-effort: a failed recording never affects the flow itself.
+    async fn maybe_record(&self, flow: &Flow) {
+        let Some(ref dir) = self.inner.service.record_path else {
+            return;
+        };
+
+        let path = dir.join(format!("{}.cast", flow.get_id()));
+        if let Err(e) = Recording::from_flow(flow).save(&path).await {
+            warn!(
+                "Failed to record flow {} to {}: {}",
+                flow.get_id(),
+                path.display(),
+                e
+            );
+        }
     }
 
     async fn handle_accept(&self) {
+        match self.inner.service.transport {
+            Transport::Tcp => self.handle_accept_tcp().await,
+            Transport::Quic => self.handle_accept_quic().await,
+        }
+    }
+
+    /// Accepts raw TCP connections and hands each off to a spawned task that
+    /// performs the (potentially slow, attacker-paced) TLS handshake under
+    /// [`ProxyInner::handshake_semaphore`], so the accept loop itself never
+    /// blocks on a single client and a burst of handshakes can't run
+    /// unbounded. Banned clients are rejected before a handshake is even
+    /// attempted.
+    async fn handle_accept_tcp(&self) {
+        let acceptor = self.inner.acceptor.as_ref().unwrap();
+
         loop {
-            let (mut client, client_addr) = match self.inner.acceptor.accept().await {
-                Ok((client, addr)) => {
-                    info!("Accepted flow from {}", addr);
-                    (client, addr)
+            let accepted = select! {
+                biased;
+                _ = self.inner.shutdown.signalled() => {
+                    info!("Shutdown signalled, no longer accepting new connections");
+                    break;
                 }
+                accepted = acceptor.accept_raw() => accepted,
+            };
+
+            let (stream, client_addr) = match accepted {
+                Ok(accepted) => accepted,
                 Err(e) => {
-                    warn!("Failed to connect to client: {}", e);
+                    warn!("Failed to accept client connection: {}", e);
                     continue;
                 }
             };
 
-            let server = match self.inner.connector.connect().await {
-                Ok(server) => server,
-                Err(e) => {
+            if self.inner.ban_list.is_banned(client_addr.ip()).await {
+                info!("Rejecting banned client {}", client_addr);
+                continue;
+            }
+
+            let Ok(permit) = self.inner.handshake_semaphore.clone().acquire_owned().await else {
+                // Semaphore is never closed, but handle it rather than panic.
+                continue;
+            };
+
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                let handshake = proxy.inner.acceptor.as_ref().unwrap().handshake(stream).await;
+                drop(permit);
+
+                match handshake {
+                    Ok((client, peer_cert_chain, negotiated_alpn)) => {
+                        info!("Accepted flow from {}", client_addr);
+                        proxy
+                            .handle_accepted_tcp(client, client_addr, peer_cert_chain, negotiated_alpn)
+                            .await;
+                    }
+                    Err(e) => warn!("Failed to complete handshake with {}: {}", client_addr, e),
+                }
+            });
+        }
+    }
+
+    /// Runs the rest of a TCP flow's setup once its (successful) handshake
+    /// has completed: dials the upstream and splices the two sides, over
+    /// HTTP or raw bytes depending on `service.http_config`.
+    async fn handle_accepted_tcp(
+        &self,
+        mut client: ProxyStream,
+        client_addr: SocketAddr,
+        peer_cert_chain: Option<Vec<rustls::pki_types::CertificateDer<'static>>>,
+        negotiated_alpn: Option<Vec<u8>>,
+    ) {
+        let connector = self.inner.connector.as_ref().unwrap();
+        let proxy = self.clone();
+        let dumper = self.inner.dumper.clone();
+
+        if let Some(http) = self.inner.service.http_config.clone() {
+                // chunk3-4 ("HTTP/2 flow parsing and per-stream filtering")
+                // is still open — HTTP-aware mode only speaks HTTP/1.1 end
+                // to end (see `HttpFlow::stream_id`). This is only the
+                // narrower fix of refusing a connection that negotiates h2
+                // over ALPN instead of silently misparsing it as HTTP/1.1.
+                if negotiated_alpn.as_deref() == Some(b"h2") {
                     warn!(
-                        "Failed to connect to service on {}: {}",
-                        self.inner.service.server_addr, e
+                        "Client {} negotiated h2, which this proxy's HTTP-aware mode doesn't support; closing",
+                        client_addr
                     );
-
                     if let Err(e) = client.shutdown().await {
                         debug!("Failed to shutdown client {}: {}", client_addr, e);
                     }
-                    continue;
+                    return;
                 }
-            };
-
-            let proxy = self.clone();
-            let dumper = self.inner.dumper.clone();
 
-            if let Some(http) = self.inner.service.http_config.clone() {
                 let mut flow = HttpFlow::new(
                     client_addr,
                     self.inner.service.client_max_history,
                     self.inner.service.server_addr,
                     self.inner.service.server_max_history,
+                    peer_cert_chain,
                 );
 
                 let client_io = TokioIo::new(client);
-                let server_io = TokioIo::new(server);
-
-                if let Ok((sender, conn)) = http.client_builder().handshake(server_io).await {
-                    run_filter!(proxy, on_http_open, &mut flow, {
-                        info!("Python client filter killed flow {}", flow.id);
-                        drop(sender);
-                        continue;
-                    });
-
-                    let upstream = tokio::spawn(conn);
-                    let service = ProxyHyper::new(proxy, sender, http.max_body, flow);
-                    let clone = service.clone();
-
-                    tokio::task::spawn(async move {
-                        match http
-                            .server_builder()
-                            .serve_connection(client_io, service)
-                            .await
-                        {
-                            Ok(_) => info!("Closed flow from {}", client_addr),
-                            Err(e) if e.is_timeout() => {
-                                info!("Closed flow from {} for timeout", client_addr)
-                            }
-                            Err(e) => warn!(
-                                "Error in flow from {}: {:?}",
-                                client_addr,
-                                e.source().unwrap_or(&e)
-                            ),
-                        };
-
-                        match upstream.await {
-                            Ok(_) => debug!("Upstream HTTP connection closed"),
-                            Err(e) => warn!("Upstream HTTP connection error: {:?}", e),
-                        };
-
-                        if let Some(flow) = clone.into_flow() {
-                            if let Some(ref channel) = dumper {
-                                if let Err(e) = channel.try_send(Flow::Http(flow)) {
-                                    warn!("Could not send flow to dumper: {}", e);
+
+                let acquired = with_retry(
+                    &self.inner.service.connect_retry,
+                    "Upstream HTTP connect",
+                    || {
+                        connector.acquire_http(
+                            client_addr,
+                            &http,
+                            negotiated_alpn.as_deref(),
+                        )
+                    },
+                )
+                .await;
+
+                let conn = match acquired {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        warn!(
+                            "Failed to connect to service on {}: {}",
+                            self.inner.service.server_addr, e
+                        );
+                        return;
+                    }
+                };
+
+                run_filter!(proxy, on_http_open, &mut flow, {
+                    info!("Python client filter killed flow {}", flow.id);
+                    connector.release_http(conn, false).await;
+                    return;
+                });
+
+                let flow_id = flow.id;
+                let cancel = self
+                    .register_flow(flow_id, client_addr, self.inner.service.server_addr, flow.start)
+                    .await;
+
+                let service = ProxyHyper::new(
+                    proxy.clone(),
+                    conn,
+                    http.max_body,
+                    http.client_body_timeout,
+                    flow,
+                );
+                let clone = service.clone();
+
+                tokio::task::spawn(async move {
+                    select! {
+                        result = http.server_builder().serve_connection(client_io, service) => {
+                            match result {
+                                Ok(_) => info!("Closed flow from {}", client_addr),
+                                Err(e) if e.is_timeout() => {
+                                    info!("Closed flow from {} for timeout", client_addr)
                                 }
+                                Err(e) => warn!(
+                                    "Error in flow from {}: {:?}",
+                                    client_addr,
+                                    e.source().unwrap_or(&e)
+                                ),
+                            };
+                        }
+
+                        // Killed by an operator or a Python filter
+                        _ = cancel.cancelled() => {
+                            info!("Flow {} killed by request", flow_id);
+                            clone.evict_and_abort().await;
+                        }
+                    }
+
+                    proxy.unregister_flow(flow_id).await;
+
+                    if let Some((conn, flow, evict)) = clone.into_parts() {
+                        proxy.inner.connector.as_ref().unwrap().release_http(conn, evict).await;
+
+                        // Guaranteed to run exactly once, however the flow
+                        // ended (clean close, error, or an earlier hook's
+                        // Break).
+                        if let Some(ref filter) = proxy.inner.service.filter {
+                            filter.on_http_close(&flow).await;
+                        }
+
+                        let flow = Flow::Http(flow);
+                        proxy.maybe_record(&flow).await;
+
+                        if let Some(ref channel) = dumper {
+                            if let Err(e) = channel.try_send(DumpItem::Flow(flow)) {
+                                warn!("Could not send flow to dumper: {}", e);
                             }
                         }
-                    });
-                }
+                    }
+                });
             } else {
+                let server = match connector
+                    .connect(client_addr, negotiated_alpn.as_deref())
+                    .await
+                {
+                    Ok(server) => server,
+                    Err(e) => {
+                        warn!(
+                            "Failed to connect to service on {}: {}",
+                            self.inner.service.server_addr, e
+                        );
+
+                        if let Err(e) = client.shutdown().await {
+                            debug!("Failed to shutdown client {}: {}", client_addr, e);
+                        }
+                        return;
+                    }
+                };
+
                 let mut flow = RawFlow::new(
                     client_addr,
                     self.inner.service.client_max_history,
                     self.inner.service.server_addr,
                     self.inner.service.server_max_history,
+                    peer_cert_chain,
+                    self.inner.service.dump_protocol,
                 );
 
                 tokio::spawn(async move {
@@ -185,14 +581,19 @@ impl Proxy {
                     debug!(
                         client_history = flow.client_history.bytes.len(),
                         client_chunks = flow.client_history.chunks.len(),
+                        client_flushed = flow.client_history.flushed,
                         server_history = flow.server_history.bytes.len(),
                         server_chunks = flow.server_history.chunks.len(),
+                        server_flushed = flow.server_history.flushed,
                         "History size for flow {}",
                         flow.id,
                     );
 
+                    let flow = Flow::Raw(flow);
+                    proxy.maybe_record(&flow).await;
+
                     if let Some(ref channel) = dumper {
-                        if let Err(e) = channel.try_send(Flow::Raw(flow)) {
+                        if let Err(e) = channel.try_send(DumpItem::Flow(flow)) {
                             warn!("Could not send flow to dumper: {}", e);
                         }
                     }
@@ -201,12 +602,159 @@ impl Proxy {
         }
     }
 
+    async fn handle_accept_quic(&self) {
+        let acceptor = &self.inner.quic.as_ref().unwrap().acceptor;
+
+        loop {
+            let accepted = select! {
+                biased;
+                _ = self.inner.shutdown.signalled() => {
+                    info!("Shutdown signalled, no longer accepting new connections");
+                    break;
+                }
+                accepted = acceptor.accept() => accepted,
+            };
+
+            let connection = match accepted {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Failed to accept QUIC connection: {}", e);
+                    continue;
+                }
+            };
+
+            let client_addr = connection.remote_address();
+            info!("Accepted QUIC connection from {}", client_addr);
+
+            if self.inner.ban_list.is_banned(client_addr.ip()).await {
+                info!("Rejecting banned client {}", client_addr);
+                connection.close(0u32.into(), b"banned");
+                continue;
+            }
+
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = proxy.handle_quic_connection(connection, client_addr).await {
+                    warn!("Error in QUIC connection from {}: {}", client_addr, e);
+                }
+            });
+        }
+    }
+
+    /// Opens the one upstream QUIC connection this client connection's
+    /// streams will be mirrored onto, then splices every bidirectional
+    /// stream the client opens into its own matching upstream stream and
+    /// synthetic [`RawFlow`], reusing [`Proxy::handle_flow`] unchanged.
+    /// Unidirectional streams and datagrams aren't handled in this first
+    /// cut.
+    async fn handle_quic_connection(
+        &self,
+        connection: quinn::Connection,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<()> {
+        let quic_transport = self.inner.quic.as_ref().unwrap();
+        let server_addr = self.inner.service.server_addr;
+        let dumper = self.inner.dumper.clone();
+
+        let upstream = quic_transport
+            .connector
+            .connect()
+            .await
+            .context("Failed to connect to service")?;
+
+        let mut next_stream_id = 0u64;
+
+        loop {
+            let (send, recv) = match connection.accept_bi().await {
+                Ok(stream) => stream,
+                Err(quinn::ConnectionError::ApplicationClosed(_)) => break,
+                Err(e) => return Err(e.into()),
+            };
+
+            let stream_id = next_stream_id;
+            next_stream_id += 1;
+
+            let (mut upstream_send, upstream_recv) = match upstream.open_bi().await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to open upstream QUIC stream: {}", e);
+                    continue;
+                }
+            };
+
+            if let Err(e) = quic_transport
+                .connector
+                .write_proxy_header(&mut upstream_send, client_addr)
+                .await
+            {
+                warn!("Failed to write PROXY protocol header: {}", e);
+                continue;
+            }
+
+            let client = quic::into_proxy_stream(send, recv);
+            let server = quic::into_proxy_stream(upstream_send, upstream_recv);
+
+            let mut flow = RawFlow::new(
+                client_addr,
+                self.inner.service.client_max_history,
+                server_addr,
+                self.inner.service.server_max_history,
+                None,
+                self.inner.service.dump_protocol,
+            );
+            flow.quic_stream_id = Some(stream_id);
+
+            let proxy = self.clone();
+            let dumper = dumper.clone();
+
+            tokio::spawn(async move {
+                match proxy.handle_flow(client, server, &mut flow).await {
+                    Ok(_) => info!("Closed QUIC stream {} from {}", stream_id, client_addr),
+                    Err(e) => warn!(
+                        "Error in QUIC stream {} from {}: {}",
+                        stream_id, client_addr, e
+                    ),
+                };
+
+                let flow = Flow::Raw(flow);
+                proxy.maybe_record(&flow).await;
+
+                if let Some(ref channel) = dumper {
+                    if let Err(e) = channel.try_send(DumpItem::Flow(flow)) {
+                        warn!("Could not send flow to dumper: {}", e);
+                    }
+                }
+            });
+        }
+
+        Ok(())
+    }
+
     #[tracing::instrument(level = "trace", skip_all)]
     async fn handle_flow(
         &self,
         mut client: ProxyStream,
         mut server: ProxyStream,
         flow: &mut RawFlow,
+    ) -> anyhow::Result<()> {
+        let result = self
+            .handle_flow_inner(&mut client, &mut server, flow)
+            .await;
+
+        // Guaranteed to run exactly once, however the flow ended (clean
+        // close, error, or an earlier hook's Break).
+        if let Some(ref filter) = self.inner.service.filter {
+            filter.on_raw_close(flow).await;
+        }
+
+        result
+    }
+
+    async fn handle_flow_inner(
+        &self,
+        client: &mut ProxyStream,
+        server: &mut ProxyStream,
+        flow: &mut RawFlow,
     ) -> anyhow::Result<()> {
         let client_timeout = self.inner.service.client_timeout;
         let server_timeout = self.inner.service.server_timeout;
@@ -216,10 +764,20 @@ impl Proxy {
             return Ok(());
         });
 
+        let cancel = self
+            .register_flow(flow.id, flow.client_addr, flow.server_addr, flow.start)
+            .await;
+
         loop {
             select! {
+                // Killed by an operator or a Python filter
+                _ = cancel.cancelled() => {
+                    info!("Flow {} killed by request", flow.id);
+                    break;
+                }
+
                 // Client -> Server
-                client_status = Self::read_chunk(&mut client, &mut flow.client_history, client_timeout) => {
+                client_status = Self::read_chunk(client, &mut flow.client_history, client_timeout) => {
                     match client_status? {
                         FlowStatus::Read => {
                             trace!(
@@ -227,17 +785,31 @@ impl Proxy {
                                 String::from_utf8_lossy(flow.client_history.last_chunk())
                             );
 
-                            run_filter!(self, on_raw_client, flow, {
-                                info!("Python client filter killed flow {}", flow.id);
-                                break;
-                            });
+                            if let Some(ref filter) = self.inner.service.filter {
+                                let verdict = filter.on_raw_client(flow).await;
+                                if let ControlFlow::Break(_) =
+                                    self.apply_raw_verdict(flow, verdict).await
+                                {
+                                    break;
+                                }
+                            }
 
                             Self::write_last_chunk(
-                                &mut server,
+                                server,
                                 &mut flow.client_history,
                                 server_timeout,
                             )
                             .await?;
+
+                            self.maybe_compact(
+                                flow.id,
+                                flow.client_addr,
+                                flow.server_addr,
+                                flow.client_addr,
+                                flow.protocol,
+                                &mut flow.client_history,
+                            )
+                            .await;
                         }
                         FlowStatus::Closed => {
                             debug!("Client sent eof");
@@ -247,15 +819,11 @@ impl Proxy {
                             info!("Client read timeout elapsed");
                             break;
                         }
-                        FlowStatus::HistoryTooBig => {
-                            warn!("Client history size reached limit, flow terminated");
-                            break;
-                        }
                     }
                 }
 
                 // Server -> Client
-                server_status = Self::read_chunk(&mut server, &mut flow.server_history, server_timeout) => {
+                server_status = Self::read_chunk(server, &mut flow.server_history, server_timeout) => {
                     match server_status? {
                         FlowStatus::Read => {
                             trace!(
@@ -263,17 +831,31 @@ impl Proxy {
                                 String::from_utf8_lossy(flow.server_history.last_chunk())
                             );
 
-                            run_filter!(self, on_raw_server, flow, {
-                                info!("Python server filter killed flow {}", flow.id);
-                                break;
-                            });
+                            if let Some(ref filter) = self.inner.service.filter {
+                                let verdict = filter.on_raw_server(flow).await;
+                                if let ControlFlow::Break(_) =
+                                    self.apply_raw_verdict(flow, verdict).await
+                                {
+                                    break;
+                                }
+                            }
 
                             Self::write_last_chunk(
-                                &mut client,
+                                client,
                                 &mut flow.server_history,
                                 client_timeout,
                             )
                             .await?;
+
+                            self.maybe_compact(
+                                flow.id,
+                                flow.client_addr,
+                                flow.server_addr,
+                                flow.server_addr,
+                                flow.protocol,
+                                &mut flow.server_history,
+                            )
+                            .await;
                         }
                         FlowStatus::Closed => {
                             debug!("Server sent eof");
@@ -283,15 +865,13 @@ impl Proxy {
                             info!("Server read timeout elapsed");
                             break;
                         }
-                        FlowStatus::HistoryTooBig => {
-                            warn!("Server history size reached limit, flow terminated");
-                            break;
-                        }
                     }
                 }
             }
         }
 
+        self.unregister_flow(flow.id).await;
+
         client.flush().await?;
         server.flush().await?;
 
@@ -306,8 +886,8 @@ impl Proxy {
         history: &mut RawHistory,
         timeout: Duration,
     ) -> anyhow::Result<FlowStatus> {
-        let start = history.bytes.len();
-        let future = stream.read_chunk(&mut history.bytes);
+        let start = history.base_offset + history.bytes.len();
+        let future = stream.read_chunk_limited(&mut history.bytes, history.max_size);
 
         match time::timeout(timeout, future).await {
             Ok(Ok(0)) => Ok(FlowStatus::Closed),
@@ -317,11 +897,7 @@ impl Proxy {
                     timestamp: Utc::now(),
                 });
 
-                if start + n >= history.max_size {
-                    Ok(FlowStatus::HistoryTooBig)
-                } else {
-                    Ok(FlowStatus::Read)
-                }
+                Ok(FlowStatus::Read)
             }
             Ok(Err(e)) => Err(e.into()),
             Err(_) => Ok(FlowStatus::Timeout),
@@ -345,3 +921,12 @@ impl Proxy {
         }
     }
 }
+
+/// Loads a [`record::Recording`] from `path` and re-fires its client-origin
+/// bytes at `service`'s upstream with the original timing, through a
+/// throwaway [`Connector`] — the `--replay` CLI mode.
+pub async fn replay_file(service: &Service, path: &Path) -> anyhow::Result<()> {
+    let recording = Recording::load(path).await?;
+    let connector = Connector::new(service).await?;
+    recording.replay(&connector).await
+}
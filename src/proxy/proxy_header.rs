@@ -0,0 +1,64 @@
+use std::net::{IpAddr, SocketAddr};
+
+/// Builds a PROXY protocol v1 (text) header carrying `src`/`dst` as the
+/// connection endpoints. Shared by [`super::connector::Connector`] (one
+/// header per upstream TCP connection) and [`super::quic::QuicConnector`]
+/// (one header per upstream QUIC stream, since each stream is its own flow).
+pub fn v1(src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    let header = match (src.ip(), dst.ip()) {
+        (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => format!(
+            "PROXY TCP4 {} {} {} {}\r\n",
+            src_ip,
+            dst_ip,
+            src.port(),
+            dst.port()
+        ),
+        (IpAddr::V6(src_ip), IpAddr::V6(dst_ip)) => format!(
+            "PROXY TCP6 {} {} {} {}\r\n",
+            src_ip,
+            dst_ip,
+            src.port(),
+            dst.port()
+        ),
+        _ => "PROXY UNKNOWN\r\n".to_string(),
+    };
+    header.into_bytes()
+}
+
+/// Builds a PROXY protocol v2 (binary) header carrying `src`/`dst` as the
+/// connection endpoints. See [`v1`] for why this is shared.
+pub fn v2(src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    const SIGNATURE: [u8; 12] = [
+        0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+    ];
+
+    let mut header = Vec::with_capacity(28);
+    header.extend_from_slice(&SIGNATURE);
+    header.push(0x21); // version 2, command PROXY
+
+    let mut addresses = Vec::with_capacity(12);
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => {
+            header.push(0x11); // AF_INET, STREAM
+            addresses.extend_from_slice(&src_ip.octets());
+            addresses.extend_from_slice(&dst_ip.octets());
+            addresses.extend_from_slice(&src.port().to_be_bytes());
+            addresses.extend_from_slice(&dst.port().to_be_bytes());
+        }
+        (IpAddr::V6(src_ip), IpAddr::V6(dst_ip)) => {
+            header.push(0x21); // AF_INET6, STREAM
+            addresses.extend_from_slice(&src_ip.octets());
+            addresses.extend_from_slice(&dst_ip.octets());
+            addresses.extend_from_slice(&src.port().to_be_bytes());
+            addresses.extend_from_slice(&dst.port().to_be_bytes());
+        }
+        _ => {
+            // Mixed address families: fall back to the unspecified/local variant
+            header.push(0x00);
+        }
+    }
+
+    header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+    header.extend_from_slice(&addresses);
+    header
+}
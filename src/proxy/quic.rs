@@ -0,0 +1,164 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use anyhow::Context as _;
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use crate::config::ProxyProtocol;
+use crate::proxy::proxy_header;
+use crate::service::Service;
+
+/// One QUIC bidirectional stream, wrapped so it satisfies
+/// [`crate::stream::ChunkStream`] (via its blanket `AsyncRead + AsyncWrite`
+/// impl) the same way a TCP/TLS socket does. Datagrams and 0-RTT data don't
+/// go through this path, only ordered stream bytes.
+pub struct QuicStream {
+    send: quinn::SendStream,
+    recv: quinn::RecvStream,
+}
+
+impl QuicStream {
+    fn new(send: quinn::SendStream, recv: quinn::RecvStream) -> QuicStream {
+        QuicStream { send, recv }
+    }
+}
+
+impl AsyncRead for QuicStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.recv).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.send).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.send).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.send).poll_shutdown(cx)
+    }
+}
+
+/// Accepts QUIC connections on `service.client_addr`, reusing the same
+/// certificate/ALPN material [`crate::tls::TlsConfig`] built for the TCP
+/// path (wrapped for QUIC via [`QuicServerConfig`]).
+pub struct QuicAcceptor {
+    endpoint: quinn::Endpoint,
+}
+
+impl QuicAcceptor {
+    pub async fn new(service: &Service) -> anyhow::Result<QuicAcceptor> {
+        let tls_config = service
+            .tls_config
+            .as_ref()
+            .context("QUIC transport requires tls_enabled")?;
+
+        let quic_server_config = QuicServerConfig::try_from((*tls_config.server_config).clone())
+            .context("TLS config isn't usable for QUIC")?;
+        let server_config = quinn::ServerConfig::with_crypto(Arc::new(quic_server_config));
+
+        let endpoint = quinn::Endpoint::server(server_config, service.client_addr)?;
+        Ok(QuicAcceptor { endpoint })
+    }
+
+    /// Accepts one QUIC connection and completes its handshake. Each
+    /// bidirectional stream the peer opens on it afterwards becomes its own
+    /// synthetic [`crate::flow::RawFlow`] — see `Proxy::handle_accept_quic`.
+    pub async fn accept(&self) -> anyhow::Result<quinn::Connection> {
+        let incoming = self
+            .endpoint
+            .accept()
+            .await
+            .context("QUIC endpoint closed")?;
+        Ok(incoming.await?)
+    }
+}
+
+/// Dials the upstream over QUIC, reusing `service.tls_config`'s client
+/// material (wrapped via [`QuicClientConfig`]) the same way [`super::connector::Connector`]
+/// does for the TCP path.
+pub struct QuicConnector {
+    endpoint: quinn::Endpoint,
+    server_addr: SocketAddr,
+    server_name: String,
+    proxy_protocol: ProxyProtocol,
+}
+
+impl QuicConnector {
+    pub async fn new(service: &Service) -> anyhow::Result<QuicConnector> {
+        let tls_config = service
+            .tls_config
+            .as_ref()
+            .context("QUIC transport requires tls_enabled")?;
+
+        let quic_client_config = QuicClientConfig::try_from((*tls_config.client_config).clone())
+            .context("TLS config isn't usable for QUIC")?;
+        let client_config = quinn::ClientConfig::new(Arc::new(quic_client_config));
+
+        let bind_addr: SocketAddr = match service.server_addr {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+
+        let mut endpoint = quinn::Endpoint::client(bind_addr)?;
+        endpoint.set_default_client_config(client_config);
+
+        Ok(QuicConnector {
+            endpoint,
+            server_addr: service.server_addr,
+            server_name: service.server_addr.ip().to_string(),
+            proxy_protocol: service.proxy_protocol,
+        })
+    }
+
+    /// Opens the single upstream QUIC connection a client connection's
+    /// streams will be mirrored onto. Called once per accepted client
+    /// connection, not once per stream.
+    pub async fn connect(&self) -> anyhow::Result<quinn::Connection> {
+        Ok(self
+            .endpoint
+            .connect(self.server_addr, &self.server_name)?
+            .await?)
+    }
+
+    /// Writes the PROXY protocol header (see [`ProxyProtocol`]) as the first
+    /// bytes on a freshly opened upstream stream, if configured. Each QUIC
+    /// stream is its own synthetic flow (see `Proxy::handle_quic_connection`),
+    /// so unlike [`super::connector::Connector`] this runs once per stream
+    /// rather than once per connection.
+    pub async fn write_proxy_header(
+        &self,
+        send: &mut quinn::SendStream,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<()> {
+        match self.proxy_protocol {
+            ProxyProtocol::Off => Ok(()),
+            ProxyProtocol::V1 => Ok(send
+                .write_all(&proxy_header::v1(client_addr, self.server_addr))
+                .await?),
+            ProxyProtocol::V2 => Ok(send
+                .write_all(&proxy_header::v2(client_addr, self.server_addr))
+                .await?),
+        }
+    }
+}
+
+/// Wraps a bidirectional stream's two halves into a [`super::ProxyStream`].
+pub fn into_proxy_stream(send: quinn::SendStream, recv: quinn::RecvStream) -> super::ProxyStream {
+    Box::pin(QuicStream::new(send, recv))
+}
@@ -0,0 +1,54 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::config::RetryPolicy;
+
+/// Runs `attempt` until it succeeds or `policy.max_retries` is exhausted,
+/// sleeping with exponential backoff (capped at `policy.max_backoff`, with
+/// jitter) between tries. `what` is only used for the warning logs.
+pub async fn with_retry<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    what: &str,
+    mut attempt: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: std::fmt::Display,
+{
+    let mut backoff = policy.initial_backoff;
+
+    for retry in 0..=policy.max_retries {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(e) if retry < policy.max_retries => {
+                warn!(
+                    "{} failed (attempt {}/{}), retrying in {:?}: {}",
+                    what,
+                    retry + 1,
+                    policy.max_retries + 1,
+                    backoff,
+                    e
+                );
+                sleep(jitter(backoff)).await;
+                backoff = backoff.mul_f64(policy.multiplier).min(policy.max_backoff);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    unreachable!("the loop above always returns on its last iteration")
+}
+
+/// Adds up to 50% of jitter on top of `backoff`, without pulling in a
+/// dependency on `rand` just for this.
+fn jitter(backoff: Duration) -> Duration {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(backoff.as_nanos() as u64);
+    let frac = (hasher.finish() % 1000) as f64 / 1000.0;
+    backoff.mul_f64(1.0 + frac * 0.5)
+}
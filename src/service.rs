@@ -1,10 +1,11 @@
 use anyhow::Context;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::config::Config;
-use crate::filter::Filter;
+use crate::config::{Config, DumpProtocol, ProxyProtocol, RetryPolicy, Transport, UpstreamTls};
+use crate::filter::chain::FilterChain;
 use crate::http::HttpConfig;
 use crate::tls::TlsConfig;
 
@@ -18,8 +19,17 @@ pub struct Service {
     pub client_max_history: usize,
     pub server_max_history: usize,
     pub tls_config: Option<TlsConfig>,
+    pub upstream_tls: UpstreamTls,
     pub http_config: Option<HttpConfig>,
-    pub filter: Option<Arc<Filter>>,
+    pub filter: Option<Arc<FilterChain>>,
+    pub proxy_protocol: ProxyProtocol,
+    pub max_pending_handshakes: usize,
+    pub transport: Transport,
+    pub dump_protocol: DumpProtocol,
+    pub connect_retry: RetryPolicy,
+    pub http_max_idle_per_host: usize,
+    pub http_idle_timeout: Duration,
+    pub record_path: Option<PathBuf>,
 }
 
 impl Service {
@@ -37,6 +47,11 @@ impl Service {
                         .as_ref()
                         .ok_or_else(|| anyhow::anyhow!("TLS key is required"))?,
                     config.tls_ca_file.as_deref(),
+                    config.tls_verify,
+                    config.tls_client_auth,
+                    &config.tls_alpn,
+                    &config.tls_sni_certs,
+                    config.tls_keylog_path.as_deref(),
                 )
                 .context("Failed to load TLS config")
             })
@@ -56,8 +71,17 @@ impl Service {
             client_max_history: config.client_max_history.as_u64() as usize,
             server_max_history: config.server_max_history.as_u64() as usize,
             tls_config,
+            upstream_tls: config.upstream_tls,
             http_config,
             filter: None,
+            proxy_protocol: config.proxy_protocol,
+            max_pending_handshakes: config.max_pending_handshakes,
+            transport: config.transport,
+            dump_protocol: config.dump_protocol,
+            connect_retry: config.connect_retry,
+            http_max_idle_per_host: config.http_max_idle_per_host,
+            http_idle_timeout: config.http_idle_timeout,
+            record_path: config.record_path.clone(),
         })
     }
 }
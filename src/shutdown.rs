@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+/// Coordinates graceful shutdown: a broadcast-style signal that tells the
+/// accept loop to stop taking new connections, plus the grace period
+/// [`crate::proxy::Proxy::shutdown`] waits for outstanding flows to drain on
+/// their own before forcing the stragglers closed.
+pub struct Shutdown {
+    token: CancellationToken,
+    grace_period: Duration,
+}
+
+impl Shutdown {
+    pub fn new(grace_period: Duration) -> Shutdown {
+        Shutdown {
+            token: CancellationToken::new(),
+            grace_period,
+        }
+    }
+
+    /// Raises the signal. Idempotent: signalling twice is a no-op.
+    pub fn signal(&self) {
+        self.token.cancel();
+    }
+
+    /// Resolves once [`Shutdown::signal`] has been called.
+    pub async fn signalled(&self) {
+        self.token.cancelled().await;
+    }
+
+    pub fn grace_period(&self) -> Duration {
+        self.grace_period
+    }
+}
@@ -8,7 +8,26 @@ use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
 
 #[async_trait]
 pub trait ChunkStream: AsyncRead + AsyncWrite + Send + Sync + Unpin {
-    async fn read_chunk(&mut self, buffer: &mut Vec<u8>) -> tokio::io::Result<usize>;
+    /// Reads until the peer would block, with no bound on how much of
+    /// `buffer` a single call may fill. Prefer
+    /// [`ChunkStream::read_chunk_limited`] wherever `buffer` feeds a
+    /// size-tracked history, so a peer that never pauses can't grow it
+    /// without bound.
+    async fn read_chunk(&mut self, buffer: &mut Vec<u8>) -> tokio::io::Result<usize> {
+        self.read_chunk_limited(buffer, usize::MAX).await
+    }
+
+    /// Like [`ChunkStream::read_chunk`], but stops pulling more bytes off
+    /// the socket once `limit` bytes have been appended to `buffer` in this
+    /// call, returning the partial read. Whatever the peer still has queued
+    /// is left in the socket for the next call instead of being buffered
+    /// here, giving the caller natural backpressure.
+    async fn read_chunk_limited(
+        &mut self,
+        buffer: &mut Vec<u8>,
+        limit: usize,
+    ) -> tokio::io::Result<usize>;
+
     async fn write_chunk(&mut self, buffer: &[u8]) -> tokio::io::Result<()>;
 }
 
@@ -17,12 +36,20 @@ impl<T> ChunkStream for T
 where
     T: AsyncRead + AsyncWrite + Send + Sync + Unpin,
 {
-    async fn read_chunk(&mut self, buffer: &mut Vec<u8>) -> tokio::io::Result<usize> {
+    async fn read_chunk_limited(
+        &mut self,
+        buffer: &mut Vec<u8>,
+        limit: usize,
+    ) -> tokio::io::Result<usize> {
         poll_fn(|cx| {
             let mut total = 0;
             let mut temp_buf = [0u8; 4096];
 
             loop {
+                if total >= limit {
+                    return Poll::Ready(Ok(total));
+                }
+
                 let mut read_buf = ReadBuf::new(&mut temp_buf);
                 let pinned = Pin::new(&mut *self);
 
@@ -1,14 +1,28 @@
+use anyhow::Context;
 use rustls::ClientConfig;
 use rustls::RootCertStore;
 use rustls::ServerConfig;
 use rustls::crypto::aws_lc_rs as provider;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
-use std::sync::Arc;
+use rustls::server::WebPkiClientVerifier;
+use rustls::sign::CertifiedKey;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::config::SniCert;
 
 #[derive(Debug, Clone)]
 pub struct TlsConfig {
     pub server_config: Arc<ServerConfig>,
     pub client_config: Arc<ClientConfig>,
+
+    /// Every TLS secret logged on either leg, in NSS `SSLKEYLOGFILE` format,
+    /// shared with [`crate::proxy::dumper::Dumper`] so it can embed them in
+    /// a pcapng Decryption Secrets Block instead of relying on an
+    /// out-of-band key log file. See [`KeyLogRecorder`].
+    pub keylog: Arc<Mutex<Vec<u8>>>,
 }
 
 impl TlsConfig {
@@ -16,9 +30,34 @@ impl TlsConfig {
         cert_path: &str,
         key_path: &str,
         ca_path: Option<&str>,
+        tls_verify: bool,
+        tls_client_auth: bool,
+        alpn_protocols: &[String],
+        sni_certs: &[SniCert],
+        keylog_path: Option<&Path>,
     ) -> anyhow::Result<TlsConfig> {
         let certs = Self::load_certificates(cert_path)?;
         let key = Self::load_private_key(key_path)?;
+        let default_key = Self::load_certified_key(certs, key)?;
+
+        let by_pattern = sni_certs
+            .iter()
+            .map(|entry| {
+                let certs = Self::load_certificates(&entry.cert_file)?;
+                let key = Self::load_private_key(&entry.key_file)?;
+                Ok((entry.sni_pattern.clone(), Self::load_certified_key(certs, key)?))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let resolver = Arc::new(SniCertResolver {
+            default: default_key,
+            by_pattern,
+        });
+
+        let alpn_protocols: Vec<Vec<u8>> = alpn_protocols
+            .iter()
+            .map(|proto| proto.as_bytes().to_vec())
+            .collect();
 
         let mut root_store = RootCertStore::empty();
         if let Some(ca_path) = ca_path {
@@ -29,25 +68,41 @@ impl TlsConfig {
             root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
         }
 
-        let mut server = ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(certs, key)?;
+        let server_builder = ServerConfig::builder();
+        let mut server = if tls_client_auth {
+            let client_verifier = WebPkiClientVerifier::builder(Arc::new(root_store.clone())).build()?;
+            server_builder
+                .with_client_cert_verifier(client_verifier)
+                .with_cert_resolver(resolver)
+        } else {
+            server_builder.with_no_client_auth().with_cert_resolver(resolver)
+        };
 
-        server.alpn_protocols = vec!["http/1.1".into()];
+        server.alpn_protocols = alpn_protocols.clone();
 
         let mut client = ClientConfig::builder()
             .with_root_certificates(root_store)
             .with_no_client_auth();
 
-        client.alpn_protocols = vec!["http/1.1".into()];
+        client.alpn_protocols = alpn_protocols;
+
+        if !tls_verify {
+            client.dangerous().set_certificate_verifier(Arc::new(
+                danger::NoCertificateVerification::new(provider::default_provider()),
+            ));
+        }
 
-        client.dangerous().set_certificate_verifier(Arc::new(
-            danger::NoCertificateVerification::new(provider::default_provider()),
-        ));
+        let key_log = Arc::new(
+            KeyLogRecorder::new(keylog_path).context("Failed to open TLS key log file")?,
+        );
+        let keylog = key_log.buf.clone();
+        server.key_log = key_log.clone();
+        client.key_log = key_log;
 
         Ok(TlsConfig {
             server_config: Arc::new(server),
             client_config: Arc::new(client),
+            keylog,
         })
     }
 
@@ -60,6 +115,103 @@ impl TlsConfig {
     pub fn load_private_key(path: &str) -> anyhow::Result<PrivateKeyDer<'static>> {
         Ok(PrivateKeyDer::from_pem_file(path)?)
     }
+
+    fn load_certified_key(
+        certs: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> anyhow::Result<Arc<CertifiedKey>> {
+        let signing_key = provider::sign::any_supported_type(&key)?;
+        Ok(Arc::new(CertifiedKey::new(certs, signing_key)))
+    }
+}
+
+/// Picks the certificate to present based on the SNI hostname the client
+/// requested, falling back to `default` when nothing matches (or the
+/// client sent no SNI at all).
+#[derive(Debug)]
+struct SniCertResolver {
+    default: Arc<CertifiedKey>,
+    by_pattern: Vec<(String, Arc<CertifiedKey>)>,
+}
+
+impl SniCertResolver {
+    /// Matches `name` against `pattern`, which may carry a single leading
+    /// `*.` wildcard covering exactly one extra label (e.g. `*.example.com`
+    /// matches `foo.example.com` but not `example.com` itself).
+    fn matches(pattern: &str, name: &str) -> bool {
+        match pattern.strip_prefix("*.") {
+            Some(suffix) => name
+                .strip_suffix(suffix)
+                .and_then(|prefix| prefix.strip_suffix('.'))
+                .is_some_and(|label| !label.is_empty() && !label.contains('.')),
+            None => pattern.eq_ignore_ascii_case(name),
+        }
+    }
+}
+
+impl rustls::server::ResolvesServerCert for SniCertResolver {
+    fn resolve(&self, client_hello: rustls::server::ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        if let Some(name) = client_hello.server_name() {
+            if let Some((_, key)) = self.by_pattern.iter().find(|(pattern, _)| Self::matches(pattern, name)) {
+                return Some(key.clone());
+            }
+        }
+
+        Some(self.default.clone())
+    }
+}
+
+/// Records TLS secrets in NSS `SSLKEYLOGFILE` format, one line per secret:
+/// `<label> <client_random_hex> <secret_hex>`. Shared between the
+/// client-facing and upstream [`rustls`] configs so both legs of a proxied
+/// connection land in the same place.
+///
+/// Always accumulates into an in-memory buffer ([`TlsConfig::keylog`]) that
+/// `proxy::dumper::Dumper` snapshots into a Decryption Secrets Block at the
+/// start of every pcapng capture (only secrets logged before that file was
+/// opened; see the dumper's `keylog` field). Additionally appends to
+/// `path`'s file when set, for [`crate::config::Config::tls_keylog_path`].
+struct KeyLogRecorder {
+    file: Option<Mutex<File>>,
+    buf: Arc<Mutex<Vec<u8>>>,
+}
+
+impl KeyLogRecorder {
+    fn new(path: Option<&Path>) -> anyhow::Result<KeyLogRecorder> {
+        let file = path
+            .map(|path| OpenOptions::new().create(true).append(true).open(path))
+            .transpose()?
+            .map(Mutex::new);
+
+        Ok(KeyLogRecorder {
+            file,
+            buf: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl rustls::KeyLog for KeyLogRecorder {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let line = format!(
+            "{} {} {}\n",
+            label,
+            Self::to_hex(client_random),
+            Self::to_hex(secret)
+        );
+
+        self.buf.lock().unwrap().extend_from_slice(line.as_bytes());
+
+        if let Some(file) = &self.file {
+            let mut file = file.lock().unwrap();
+            if let Err(e) = file.write_all(line.as_bytes()) {
+                tracing::warn!("Failed to write TLS key log entry: {}", e);
+            }
+        }
+    }
 }
 
 mod danger {
@@ -0,0 +1,271 @@
+use base64::Engine;
+use bytes::Bytes;
+use http::{HeaderMap, Method};
+use pyo3::types::{PyAnyMethods, PyBytesMethods, PyStringMethods};
+use pyo3::{Bound, FromPyObject, IntoPyObject, Py, PyAny, PyErr, PyResult, Python};
+use sha1::{Digest, Sha1};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::filter::api::PyWsFrame;
+
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode {
+    fn from_byte(b: u8) -> Option<Opcode> {
+        match b {
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xA => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xA,
+        }
+    }
+
+    /// Name exposed to the python filter API.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Opcode::Continuation => "continuation",
+            Opcode::Text => "text",
+            Opcode::Binary => "binary",
+            Opcode::Close => "close",
+            Opcode::Ping => "ping",
+            Opcode::Pong => "pong",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Opcode> {
+        match name {
+            "continuation" => Some(Opcode::Continuation),
+            "text" => Some(Opcode::Text),
+            "binary" => Some(Opcode::Binary),
+            "close" => Some(Opcode::Close),
+            "ping" => Some(Opcode::Ping),
+            "pong" => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WsFrame {
+    pub opcode: Opcode,
+    pub fin: bool,
+    pub payload: Bytes,
+}
+
+/// Whether `headers` name a valid WebSocket upgrade request per RFC 6455 —
+/// `Connection: Upgrade`, `Upgrade: websocket`, a `Sec-WebSocket-Key` and
+/// version 13.
+pub fn is_upgrade_request(method: &Method, headers: &HeaderMap) -> bool {
+    if method != Method::GET {
+        return false;
+    }
+
+    let has_token = |name: http::HeaderName, token: &str| {
+        headers
+            .get(&name)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+    };
+
+    has_token(http::header::CONNECTION, "upgrade")
+        && has_token(http::header::UPGRADE, "websocket")
+        && headers.contains_key("sec-websocket-key")
+        && headers
+            .get("sec-websocket-version")
+            .and_then(|v| v.to_str().ok())
+            == Some("13")
+}
+
+/// Computes the `Sec-WebSocket-Accept` value for a client's
+/// `Sec-WebSocket-Key`.
+pub fn accept_key(client_key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(client_key.as_bytes());
+    hasher.update(WS_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// Reads one WebSocket frame off `stream`, unmasking the payload if masked.
+/// Returns `None` on a clean EOF before any byte of a new frame was read.
+/// Bounded by `max_size` to avoid unbounded allocation from a hostile peer.
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    max_size: usize,
+) -> anyhow::Result<Option<WsFrame>> {
+    let mut head = [0u8; 2];
+    if let Err(e) = stream.read_exact(&mut head).await {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            return Ok(None);
+        }
+        return Err(e.into());
+    }
+
+    let fin = head[0] & 0x80 != 0;
+    let opcode = Opcode::from_byte(head[0] & 0x0F)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported WebSocket opcode"))?;
+    let masked = head[1] & 0x80 != 0;
+    let mut len = (head[1] & 0x7F) as u64;
+
+    if len == 126 {
+        let mut ext = [0u8; 2];
+        stream.read_exact(&mut ext).await?;
+        len = u16::from_be_bytes(ext) as u64;
+    } else if len == 127 {
+        let mut ext = [0u8; 8];
+        stream.read_exact(&mut ext).await?;
+        len = u64::from_be_bytes(ext);
+    }
+
+    if len as usize > max_size {
+        anyhow::bail!("WebSocket frame exceeds the {} byte limit", max_size);
+    }
+
+    let mask = if masked {
+        let mut mask = [0u8; 4];
+        stream.read_exact(&mut mask).await?;
+        Some(mask)
+    } else {
+        None
+    };
+
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload).await?;
+
+    if let Some(mask) = mask {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+
+    Ok(Some(WsFrame {
+        opcode,
+        fin,
+        payload: Bytes::from(payload),
+    }))
+}
+
+/// Serializes `frame` for the wire. Per RFC 6455, frames sent from a client
+/// to a server must be masked; frames sent from a server to a client must
+/// not be — `mask` selects which side we're writing as.
+pub fn encode_frame(frame: &WsFrame, mask: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(frame.payload.len() + 14);
+
+    out.push((if frame.fin { 0x80 } else { 0 }) | frame.opcode.to_byte());
+
+    let mask_bit = if mask { 0x80 } else { 0 };
+    let len = frame.payload.len();
+
+    if len < 126 {
+        out.push(mask_bit | len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(mask_bit | 126);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(mask_bit | 127);
+        out.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+
+    if mask {
+        let key = mask_key();
+        out.extend_from_slice(&key);
+        out.extend(
+            frame
+                .payload
+                .iter()
+                .enumerate()
+                .map(|(i, b)| b ^ key[i % 4]),
+        );
+    } else {
+        out.extend_from_slice(&frame.payload);
+    }
+
+    out
+}
+
+/// A 4-byte masking key, without pulling in a dependency on `rand` just for
+/// this (same trick as `proxy::retry::jitter`).
+fn mask_key() -> [u8; 4] {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish().to_le_bytes()[..4].try_into().unwrap()
+}
+
+impl<'py> IntoPyObject<'py> for WsFrame {
+    type Target = PyWsFrame;
+    type Output = Bound<'py, Self::Target>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        let frame: Py<PyWsFrame> = Py::new(py, PyWsFrame::new(self))?;
+        Ok(frame.into_bound(py))
+    }
+}
+
+impl<'py> FromPyObject<'py> for WsFrame {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let frame_bound: &Bound<'py, PyWsFrame> = ob.downcast()?;
+        let mut frame = frame_bound.borrow_mut();
+
+        let inner = frame.frame.take().unwrap_or_else(|| WsFrame {
+            opcode: Opcode::Binary,
+            fin: true,
+            payload: Bytes::new(),
+        });
+
+        let opcode = if let Some(ref opcode) = frame.opcode {
+            let name = opcode.bind(ob.py()).to_str()?;
+            Opcode::from_name(name).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Invalid opcode: {}",
+                    name
+                ))
+            })?
+        } else {
+            inner.opcode
+        };
+
+        let payload = if let Some(ref payload) = frame.payload {
+            Bytes::copy_from_slice(&payload.bind(ob.py()).as_bytes())
+        } else {
+            inner.payload
+        };
+
+        let fin = frame.fin.unwrap_or(inner.fin);
+
+        Ok(WsFrame {
+            opcode,
+            fin,
+            payload,
+        })
+    }
+}